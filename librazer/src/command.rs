@@ -1,9 +1,11 @@
 use crate::device::Device;
 use crate::packet::Packet;
 use crate::types::{
-    BatteryCare, Cluster, CpuBoost, FanMode, FanZone, GpuBoost, LightsAlwaysOn, LogoMode,
-    MaxFanSpeedMode, PerfMode,
+    ActiveGpu, BatteryCare, BatteryHealth, BatteryStatus, Cluster, CpuBoost, FanMode, FanZone,
+    GpuBoost, LightingProfile, LightsAlwaysOn, LogoMode, MaxFanSpeedMode, PerfMode, PerfState, Rgb,
+    ThrottleState,
 };
+use crate::Error;
 use anyhow::{bail, ensure, Result};
 use log::debug;
 
@@ -14,15 +16,27 @@ fn _send_command(device: &Device, command: u16, args: &[u8]) -> Result<Packet> {
 }
 
 fn _set_perf_mode(device: &Device, perf_mode: PerfMode, fan_mode: FanMode) -> Result<()> {
-    if (fan_mode == FanMode::Manual) && (perf_mode != PerfMode::Balanced && perf_mode != PerfMode::Turbo) {
-        bail!("{:?} allowed only in {:?}", fan_mode, PerfMode::Balanced);
+    ensure!(
+        device.info.perf_modes.contains(&perf_mode),
+        Error::Unsupported(format!(
+            "{:?} mode is not supported by {}",
+            perf_mode, device.info.name
+        ))
+    );
+    if fan_mode == FanMode::Manual && !perf_mode.takes_manual_fan() {
+        bail!(
+            "{:?} allowed only in {:?} and {:?}",
+            fan_mode,
+            PerfMode::Balanced,
+            PerfMode::Turbo
+        );
     }
 
     [1, 2].into_iter().try_for_each(|zone| {
         _send_command(
             device,
             0x0d02, // HID command 0x0d02 controls performance modes
-            &[0x01, zone, perf_mode as u8, fan_mode as u8],
+            &[0x01, zone, perf_mode.into(), fan_mode.into()],
         )
         .map(|_| ())
     })
@@ -31,7 +45,11 @@ fn _set_perf_mode(device: &Device, perf_mode: PerfMode, fan_mode: FanMode) -> Re
 fn _set_boost(device: &Device, cluster: Cluster, boost: u8) -> Result<()> {
     let args = &[0, cluster as u8, boost];
     ensure!(
-        get_perf_mode(device)? == (PerfMode::Custom, FanMode::Auto),
+        get_perf_state(device)?
+            == PerfState {
+                perf_mode: PerfMode::Custom,
+                fan_mode: FanMode::Auto
+            },
         "Performance mode must be {:?}",
         PerfMode::Custom
     );
@@ -52,13 +70,19 @@ pub fn set_perf_mode(device: &Device, perf_mode: PerfMode) -> Result<()> {
     _set_perf_mode(device, perf_mode, FanMode::Auto)
 }
 
+#[deprecated(note = "use get_perf_state, the tuple will be removed in the next release")]
 pub fn get_perf_mode(device: &Device) -> Result<(PerfMode, FanMode)> {
-    let [r1, r2]: [Result<(PerfMode, FanMode)>; 2] = [1, 2].map(|zone| {
+    let state = get_perf_state(device)?;
+    Ok((state.perf_mode, state.fan_mode))
+}
+
+pub fn get_perf_state(device: &Device) -> Result<PerfState> {
+    let [r1, r2]: [Result<PerfState>; 2] = [1, 2].map(|zone| {
         let response = device.send(Packet::new(0x0d82, &[0, zone, 0, 0]))?;
-        Ok((
-            PerfMode::try_from(response.get_args()[2])?,
-            FanMode::try_from(response.get_args()[3])?,
-        ))
+        Ok(PerfState {
+            perf_mode: PerfMode::try_from(response.get_args()[2])?,
+            fan_mode: FanMode::try_from(response.get_args()[3])?,
+        })
     });
 
     ensure!(
@@ -77,11 +101,49 @@ pub fn get_perf_mode(device: &Device) -> Result<(PerfMode, FanMode)> {
 }
 
 pub fn set_cpu_boost(device: &Device, boost: CpuBoost) -> Result<()> {
-    _set_boost(device, Cluster::Cpu, boost as u8)
+    _set_boost(device, Cluster::Cpu, boost.into())
 }
 
 pub fn set_gpu_boost(device: &Device, boost: GpuBoost) -> Result<()> {
-    _set_boost(device, Cluster::Gpu, boost as u8)
+    _set_boost(device, Cluster::Gpu, boost.into())
+}
+
+fn _ensure_boost_level(device: &Device, cluster: Cluster, level: u8) -> Result<()> {
+    let max = device.info.boost_levels.map(|(cpu, gpu)| match cluster {
+        Cluster::Cpu => cpu,
+        Cluster::Gpu => gpu,
+    });
+    match max {
+        Some(max) if level <= max => Ok(()),
+        Some(max) => bail!(Error::Unsupported(format!(
+            "{:?} boost level {} is above the maximum {} of {}",
+            cluster, level, max, device.info.name
+        ))),
+        None => bail!(Error::Unsupported(format!(
+            "{} only takes the named boost levels",
+            device.info.name
+        ))),
+    }
+}
+
+/// Sets a raw firmware level, for models whose descriptor has `boost_levels`
+pub fn set_cpu_boost_level(device: &Device, level: u8) -> Result<()> {
+    _ensure_boost_level(device, Cluster::Cpu, level)?;
+    _set_boost(device, Cluster::Cpu, level)
+}
+
+pub fn set_gpu_boost_level(device: &Device, level: u8) -> Result<()> {
+    _ensure_boost_level(device, Cluster::Gpu, level)?;
+    _set_boost(device, Cluster::Gpu, level)
+}
+
+/// Raw level as reported by the firmware, which may have no `CpuBoost` equivalent
+pub fn get_cpu_boost_level(device: &Device) -> Result<u8> {
+    _get_boost(device, Cluster::Cpu)
+}
+
+pub fn get_gpu_boost_level(device: &Device) -> Result<u8> {
+    _get_boost(device, Cluster::Gpu)
 }
 
 pub fn get_cpu_boost(device: &Device) -> Result<CpuBoost> {
@@ -92,55 +154,113 @@ pub fn get_gpu_boost(device: &Device) -> Result<GpuBoost> {
     GpuBoost::try_from(_get_boost(device, Cluster::Gpu)?)
 }
 
-pub fn set_fan_rpm(device: &Device, rpm: u16) -> Result<()> {
-    ensure!((2000..=5000).contains(&rpm));
+/// Lowest and highest RPM `set_fan_rpm` accepts. No HID command reporting the
+/// firmware's limits is known, so they come from the descriptor.
+pub fn get_fan_rpm_limits(device: &Device) -> Result<(u16, u16)> {
+    Ok(device.info.fan_rpm_limits)
+}
+
+// the fan commands carry RPM as a single byte in hundreds
+const FAN_RPM_STEP: u16 = 100;
+
+/// Resolution of the fan RPM: `set_fan_rpm` cuts a value down to a multiple of it and
+/// `get_fan_rpm` reads back multiples, so readbacks compare within one step.
+pub fn get_fan_rpm_step(_device: &Device) -> Result<u16> {
+    Ok(FAN_RPM_STEP)
+}
+
+pub fn set_fan_rpm(device: &Device, fan_zone: FanZone, rpm: u16) -> Result<()> {
+    let (min, max) = get_fan_rpm_limits(device)?;
+    ensure!(
+        (min..=max).contains(&rpm),
+        "Fan RPM must be in range [{}, {}] on {}",
+        min,
+        max,
+        device.info.name
+    );
+    let state = get_perf_state(device)?;
     ensure!(
-        get_perf_mode(device)? == (PerfMode::Balanced, FanMode::Manual),
-        "Performance mode must be {:?} and fan mode must be {:?}",
+        state.perf_mode.takes_manual_fan() && state.fan_mode == FanMode::Manual,
+        "Performance mode must be {:?} or {:?} and fan mode must be {:?}",
         PerfMode::Balanced,
+        PerfMode::Turbo,
         FanMode::Manual
     );
-    [FanZone::Zone1, FanZone::Zone2]
-        .into_iter()
-        .try_for_each(|zone| {
-            _send_command(device, 0x0d01, &[0, zone as u8, (rpm / 100) as u8]).map(|_| ())
-        })
+    fan_zone.zones().iter().try_for_each(|&zone| {
+        _send_command(device, 0x0d01, &[0, zone as u8, (rpm / FAN_RPM_STEP) as u8]).map(|_| ())
+    })
 }
 
-pub fn get_fan_rpm(device: &Device, fan_zone: FanZone) -> Result<u16> {
-    let response = device.send(Packet::new(0x0d81, &[0, fan_zone as u8, 0]))?;
-    ensure!(response.get_args()[1] == fan_zone as u8);
-    Ok(response.get_args()[2] as u16 * 100)
+// One RPM per zone in `fan_zone`, in zone order
+pub fn get_fan_rpm(device: &Device, fan_zone: FanZone) -> Result<Vec<u16>> {
+    fan_zone
+        .zones()
+        .iter()
+        .map(|&zone| {
+            let response = device.send(Packet::new(0x0d81, &[0, zone as u8, 0]))?;
+            ensure!(response.get_args()[1] == zone as u8);
+            Ok(response.get_args()[2] as u16 * FAN_RPM_STEP)
+        })
+        .collect()
 }
 
 pub fn set_max_fan_speed_mode(device: &Device, mode: MaxFanSpeedMode) -> Result<()> {
     ensure!(
-        get_perf_mode(device)?.0 == PerfMode::Custom,
+        get_perf_state(device)?.perf_mode == PerfMode::Custom,
         "Performance mode must be {:?}",
         PerfMode::Custom
     );
-    _send_command(device, 0x070f, &[mode as u8]).map(|_| ())
+    _send_command(device, 0x070f, &[mode.into()]).map(|_| ())
 }
 
 pub fn get_max_fan_speed_mode(device: &Device) -> Result<MaxFanSpeedMode> {
     device.send(Packet::new(0x078f, &[0]))?.get_args()[0].try_into()
 }
 
+/// Sets the fan mode in the current performance mode, then reads the fan mode back
 pub fn set_fan_mode(device: &Device, mode: FanMode) -> Result<()> {
+    // kept as is, the fan mode is sent along with it
+    let perf_mode = get_perf_state(device)?.perf_mode;
     ensure!(
-        get_perf_mode(device)?.0 == PerfMode::Balanced,
-        "Performance mode must be {:?}",
-        PerfMode::Balanced
+        perf_mode.takes_manual_fan(),
+        "Performance mode must be {:?} or {:?}",
+        PerfMode::Balanced,
+        PerfMode::Turbo
+    );
+    _set_perf_mode(device, perf_mode, mode)?;
+    let actual = get_perf_state(device)?.fan_mode;
+    ensure!(
+        actual == mode,
+        Error::VerificationFailed {
+            expected: format!("{:?}", mode),
+            actual: format!("{:?}", actual),
+        }
     );
-    _set_perf_mode(device, PerfMode::Balanced, mode)
+    Ok(())
 }
 
-pub fn custom_command(device: &Device, command: u16, args: &[u8]) -> Result<()> {
+/// Sends `value` with `set` only when `get` reports something else, returning whether it
+/// was sent. Every call pays for a read to maybe save a write: worth it for daemons
+/// re-asserting the same settings, slower for one-off changes.
+pub fn set_if_changed<T: PartialEq>(
+    device: &Device,
+    value: T,
+    get: impl FnOnce(&Device) -> Result<T>,
+    set: impl FnOnce(&Device, T) -> Result<()>,
+) -> Result<bool> {
+    if get(device)? == value {
+        return Ok(false);
+    }
+    set(device, value)?;
+    Ok(true)
+}
+
+pub fn custom_command(device: &Device, command: u16, args: &[u8]) -> Result<Packet> {
     let report = Packet::new(command, args);
     debug!("Report   {:?}", report);
     let response = device.send(report)?;
     debug!("Response {:?}", response);
-    Ok(())
+    Ok(response)
 }
 
 fn _set_logo_power(device: &Device, mode: LogoMode) -> Result<Packet> {
@@ -210,7 +330,7 @@ pub fn get_lights_always_on(device: &Device) -> Result<LightsAlwaysOn> {
 }
 
 pub fn set_lights_always_on(device: &Device, lights_always_on: LightsAlwaysOn) -> Result<()> {
-    let args = &[lights_always_on as u8, 0];
+    let args = &[lights_always_on.into(), 0];
     ensure!(device
         .send(Packet::new(0x0004, args))?
         .get_args()
@@ -222,11 +342,500 @@ pub fn get_battery_care(device: &Device) -> Result<BatteryCare> {
     device.send(Packet::new(0x0792, &[0]))?.get_args()[0].try_into()
 }
 
+/// Charge level battery care stops at, in percent. The firmware reports it in the low
+/// 7 bits of the battery care byte, the top bit tells whether care is enabled.
+pub fn get_battery_care_threshold(device: &Device) -> Result<u8> {
+    Ok(device.send(Packet::new(0x0792, &[0]))?.get_args()[0] & 0x7f)
+}
+
 pub fn set_battery_care(device: &Device, mode: BatteryCare) -> Result<()> {
-    let args = &[mode as u8];
+    let args = &[mode.into()];
     ensure!(device
         .send(Packet::new(0x0712, args))?
         .get_args()
         .starts_with(args));
     Ok(())
 }
+
+/// Shows `frame`, one row of colors per keyboard row, matching the descriptor's key matrix.
+/// Uses the standard matrix custom frame commands, see https://github.com/openrazer/openrazer
+pub fn set_per_key_colors(device: &Device, frame: &[Vec<Rgb>]) -> Result<()> {
+    let Some((rows, cols)) = device.info.key_matrix else {
+        bail!(Error::Unsupported(format!(
+            "{} has no per-key backlight",
+            device.info.name
+        )));
+    };
+    ensure!(
+        frame.len() == rows,
+        "Layout has {} rows, {} has {}",
+        frame.len(),
+        device.info.name,
+        rows
+    );
+    if let Some((row, keys)) = frame
+        .iter()
+        .enumerate()
+        .find(|(_, keys)| keys.len() != cols)
+    {
+        bail!(
+            "Layout row {} has {} keys, {} has {}",
+            row,
+            keys.len(),
+            device.info.name,
+            cols
+        );
+    }
+
+    frame.iter().enumerate().try_for_each(|(row, keys)| {
+        // frame id, row, first and last column, then rgb per key
+        let args: Vec<u8> = [0xff, row as u8, 0, (cols - 1) as u8]
+            .into_iter()
+            .chain(keys.iter().flat_map(|key| [key.r, key.g, key.b]))
+            .collect();
+        device.send(Packet::new(0x030b, &args)).map(|_| ())
+    })?;
+
+    // switch to the custom frame effect, not stored
+    device.send(Packet::new(0x030a, &[0x05, 0x00])).map(|_| ())
+}
+
+pub fn set_static_color(device: &Device, color: Rgb) -> Result<()> {
+    ensure!(
+        device.info.key_matrix.is_some(),
+        Error::Unsupported(format!("{} has no per-key backlight", device.info.name))
+    );
+    device
+        .send(Packet::new(0x030a, &[0x06, color.r, color.g, color.b]))
+        .map(|_| ())
+}
+
+/// Sets the brightness and color of `profile` so the change shows as one step. No model
+/// is known to have begin/commit framing for lighting, so on every supported model these
+/// are sequential writes, ordered so the state in between is never brighter than both
+/// ends: dimming before the color change, brightening after it. A brightness that is
+/// already set is not sent again.
+pub fn set_lighting_profile(device: &Device, profile: &LightingProfile) -> Result<()> {
+    // checked before anything is sent, so a failing color doesn't leave a new brightness
+    ensure!(
+        profile.color.is_none() || device.info.key_matrix.is_some(),
+        Error::Unsupported(format!("{} has no per-key backlight", device.info.name))
+    );
+    let brightness = match profile.brightness {
+        Some(brightness) => Some((brightness, get_keyboard_brightness(device)?)),
+        None => None,
+    };
+    if let Some((brightness, _)) = brightness.filter(|(new, old)| new < old) {
+        set_keyboard_brightness(device, brightness)?;
+    }
+    if let Some(color) = profile.color {
+        set_static_color(device, color)?;
+    }
+    if let Some((brightness, _)) = brightness.filter(|(new, old)| new > old) {
+        set_keyboard_brightness(device, brightness)?;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn _read_sysfs_number(path: &std::path::Path, name: &str) -> Option<u64> {
+    std::fs::read_to_string(path.join(name))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// No HID command for the battery capacity is known, so this reads the OS battery
+/// information instead, /sys/class/power_supply/BAT* on Linux
+#[cfg(target_os = "linux")]
+pub fn get_battery_health(_device: &Device) -> Result<BatteryHealth> {
+    let batteries = std::fs::read_dir("/sys/class/power_supply")?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("BAT"))
+        });
+    for battery in batteries {
+        let read = |name| _read_sysfs_number(&battery, name);
+        // energy in µWh, or charge in µAh that needs the design voltage in µV
+        let capacity = match (read("energy_full_design"), read("energy_full")) {
+            (Some(design), Some(full)) => Some((design / 1000, full / 1000)),
+            _ => match (
+                read("charge_full_design"),
+                read("charge_full"),
+                read("voltage_min_design"),
+            ) {
+                (Some(design), Some(full), Some(voltage)) => Some((
+                    design * voltage / 1_000_000_000,
+                    full * voltage / 1_000_000_000,
+                )),
+                _ => None,
+            },
+        };
+        if let Some((design, full)) = capacity {
+            return Ok(BatteryHealth {
+                design_mwh: design as u32,
+                full_mwh: full as u32,
+                cycle_count: read("cycle_count").map(|count| count as u32),
+            });
+        }
+    }
+    bail!(Error::Unsupported(
+        "No battery capacity information found".into()
+    ))
+}
+
+#[cfg(target_os = "linux")]
+const NVIDIA_VENDOR_ID: u64 = 0x10de;
+
+#[cfg(target_os = "linux")]
+fn _read_pci_vendor(device: &std::path::Path) -> Option<u64> {
+    let vendor = std::fs::read_to_string(device.join("vendor")).ok()?;
+    u64::from_str_radix(vendor.trim().trim_start_matches("0x"), 16).ok()
+}
+
+/// No HID command for the active GPU is known, so this asks the OS which GPU drives the
+/// display, the one with `boot_vga` set under /sys/class/drm on Linux. Razer's discrete
+/// GPUs are all NVIDIA, any other vendor is taken for the integrated one.
+#[cfg(target_os = "linux")]
+pub fn get_active_gpu(_device: &Device) -> Result<ActiveGpu> {
+    let vendor = std::fs::read_dir("/sys/class/drm")?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path().join("device"))
+        .filter(|device| _read_sysfs_number(device, "boot_vga") == Some(1))
+        .find_map(|device| _read_pci_vendor(&device));
+    match vendor {
+        Some(NVIDIA_VENDOR_ID) => Ok(ActiveGpu::Discrete),
+        Some(_) => Ok(ActiveGpu::Integrated),
+        None => bail!(Error::Unsupported(
+            "No GPU drives the display according to /sys/class/drm".into()
+        )),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn get_active_gpu(_device: &Device) -> Result<ActiveGpu> {
+    bail!(Error::Unsupported(
+        "The active GPU is only read on Linux".into()
+    ))
+}
+
+/// Charge level and whether AC is connected, read from the OS like `get_battery_health`
+#[cfg(target_os = "linux")]
+pub fn get_battery_status(_device: &Device) -> Result<BatteryStatus> {
+    let supplies: Vec<_> = std::fs::read_dir("/sys/class/power_supply")?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect();
+    let is_type = |path: &std::path::Path, kind: &str| {
+        std::fs::read_to_string(path.join("type")).is_ok_and(|t| t.trim() == kind)
+    };
+    let level = supplies
+        .iter()
+        .filter(|path| is_type(path, "Battery"))
+        .find_map(|path| _read_sysfs_number(path, "capacity"));
+    let Some(level) = level else {
+        bail!(Error::Unsupported(
+            "No battery level information found".into()
+        ));
+    };
+    Ok(BatteryStatus {
+        level_pct: level.min(100) as u8,
+        plugged_in: supplies
+            .iter()
+            .filter(|path| is_type(path, "Mains"))
+            .any(|path| _read_sysfs_number(path, "online") == Some(1)),
+    })
+}
+
+/// Charge level and whether AC is connected, from GetSystemPowerStatus
+#[cfg(windows)]
+pub fn get_battery_status(_device: &Device) -> Result<BatteryStatus> {
+    use windows_sys::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+    // BatteryFlag bit for "no system battery", and the value of unknown fields
+    const NO_BATTERY: u8 = 0x80;
+    const UNKNOWN: u8 = 0xff;
+    // SAFETY: a plain C struct of integers, all zero is a valid value
+    let mut status: SYSTEM_POWER_STATUS = unsafe { std::mem::zeroed() };
+    // SAFETY: only writes the struct it is given
+    if unsafe { GetSystemPowerStatus(&mut status) } == 0 {
+        bail!(std::io::Error::last_os_error());
+    }
+    ensure!(
+        status.BatteryFlag & NO_BATTERY == 0 && status.BatteryLifePercent != UNKNOWN,
+        Error::Unsupported("No battery level information found".into())
+    );
+    ensure!(
+        status.ACLineStatus != UNKNOWN,
+        Error::Unsupported("The AC line status is unknown".into())
+    );
+    Ok(BatteryStatus {
+        level_pct: status.BatteryLifePercent.min(100),
+        plugged_in: status.ACLineStatus == 1,
+    })
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn get_battery_status(_device: &Device) -> Result<BatteryStatus> {
+    bail!(Error::Unsupported(
+        "Battery status is only read on Linux and Windows".into()
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn get_battery_health(_device: &Device) -> Result<BatteryHealth> {
+    bail!(Error::Unsupported(
+        "Battery health is only read on Linux".into()
+    ))
+}
+
+// CPU package zone of RAPL, registered under this name by both Intel and AMD kernels
+#[cfg(target_os = "linux")]
+const RAPL_PACKAGE: &str = "/sys/class/powercap/intel-rapl:0";
+
+// the energy counter is read twice this far apart and averaged in between
+#[cfg(target_os = "linux")]
+const POWER_SAMPLE: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// No HID command reports power draw or clocks either, so this averages the OS counter
+/// of the CPU package energy over 200 ms, RAPL under /sys/class/powercap on Linux.
+/// Kernels since 5.10 only let root read it.
+#[cfg(target_os = "linux")]
+pub fn get_cpu_power(_device: &Device) -> Result<f32> {
+    let package = std::path::Path::new(RAPL_PACKAGE);
+    let read = || match std::fs::read_to_string(package.join("energy_uj")) {
+        Ok(energy) => Ok(energy.trim().parse::<u64>()?),
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            bail!(Error::PermissionDenied(format!(
+                "Reading the CPU energy counter in {} needs root",
+                RAPL_PACKAGE
+            )))
+        }
+        Err(_) => bail!(Error::Unsupported(
+            "No CPU energy counter found under /sys/class/powercap".into()
+        )),
+    };
+    let before = read()?;
+    std::thread::sleep(POWER_SAMPLE);
+    let after = read()?;
+    // µJ, wrapping around at the range
+    let used = match after.checked_sub(before) {
+        Some(used) => used,
+        None => {
+            _read_sysfs_number(package, "max_energy_range_uj")
+                .unwrap_or(u64::MAX)
+                .saturating_sub(before)
+                + after
+        }
+    };
+    Ok(used as f32 / POWER_SAMPLE.as_micros() as f32)
+}
+
+/// Clock of the fastest core in MHz, read from cpufreq under /sys/devices/system/cpu
+#[cfg(target_os = "linux")]
+pub fn get_cpu_clock(_device: &Device) -> Result<u32> {
+    let khz = std::fs::read_dir("/sys/devices/system/cpu")?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| _read_sysfs_number(&entry.path().join("cpufreq"), "scaling_cur_freq"))
+        .max();
+    match khz {
+        Some(khz) => Ok((khz / 1000) as u32),
+        None => bail!(Error::Unsupported(
+            "No CPU clock found under /sys/devices/system/cpu".into()
+        )),
+    }
+}
+
+// (core events summed over the CPUs, package events) from thermal_throttle, the CPUs of a
+// package all count its events
+#[cfg(target_os = "linux")]
+fn _read_throttle_counts() -> Option<(u64, u64)> {
+    let counters: Vec<_> = std::fs::read_dir("/sys/devices/system/cpu")
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path().join("thermal_throttle"))
+        .filter(|path| path.is_dir())
+        .collect();
+    if counters.is_empty() {
+        return None;
+    }
+    let read = |name| {
+        counters
+            .iter()
+            .filter_map(move |path| _read_sysfs_number(path, name))
+    };
+    Some((
+        read("core_throttle_count").sum(),
+        read("package_throttle_count").max().unwrap_or(0),
+    ))
+}
+
+/// Whether the CPU throttles, from the kernel's thermal_throttle counters under
+/// /sys/devices/system/cpu, read twice 200 ms apart. Only Intel CPUs have them.
+#[cfg(target_os = "linux")]
+pub fn get_throttle_state(_device: &Device) -> Result<ThrottleState> {
+    let Some((core_before, package_before)) = _read_throttle_counts() else {
+        bail!(Error::Unsupported(
+            "No thermal_throttle counters found under /sys/devices/system/cpu".into()
+        ));
+    };
+    std::thread::sleep(POWER_SAMPLE);
+    let (core_events, package_events) =
+        _read_throttle_counts().unwrap_or((core_before, package_before));
+    Ok(ThrottleState {
+        core: core_events > core_before,
+        package: package_events > package_before,
+        core_events,
+        package_events,
+    })
+}
+
+// Reading `name` from the hwmon of the NVIDIA GPU's driver, the discrete one as in
+// `get_active_gpu`. The proprietary driver registers no hwmon, only nouveau does.
+#[cfg(target_os = "linux")]
+fn _read_discrete_gpu_sensor(name: &str) -> Option<u64> {
+    std::fs::read_dir("/sys/class/drm")
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path().join("device"))
+        .filter(|device| _read_pci_vendor(device) == Some(NVIDIA_VENDOR_ID))
+        .filter_map(|device| std::fs::read_dir(device.join("hwmon")).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .find_map(|hwmon| _read_sysfs_number(&hwmon.path(), name))
+}
+
+/// Power draw of the discrete GPU in watts, from its driver's hwmon sensors
+#[cfg(target_os = "linux")]
+pub fn get_gpu_power(_device: &Device) -> Result<f32> {
+    // µW
+    match _read_discrete_gpu_sensor("power1_average")
+        .or_else(|| _read_discrete_gpu_sensor("power1_input"))
+    {
+        Some(power) => Ok(power as f32 / 1_000_000.0),
+        None => bail!(Error::Unsupported(
+            "No GPU power sensor found in hwmon, the NVIDIA driver only reports it to nvidia-smi"
+                .into()
+        )),
+    }
+}
+
+/// Core clock of the discrete GPU in MHz, from its driver's hwmon sensors
+#[cfg(target_os = "linux")]
+pub fn get_gpu_clock(_device: &Device) -> Result<u32> {
+    // Hz
+    match _read_discrete_gpu_sensor("freq1_input") {
+        Some(clock) => Ok((clock / 1_000_000) as u32),
+        None => bail!(Error::Unsupported(
+            "No GPU clock sensor found in hwmon, the NVIDIA driver only reports it to nvidia-smi"
+                .into()
+        )),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn get_cpu_power(_device: &Device) -> Result<f32> {
+    bail!(Error::Unsupported("CPU power is only read on Linux".into()))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn get_cpu_clock(_device: &Device) -> Result<u32> {
+    bail!(Error::Unsupported("CPU clock is only read on Linux".into()))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn get_throttle_state(_device: &Device) -> Result<ThrottleState> {
+    bail!(Error::Unsupported(
+        "Throttling is only read on Linux".into()
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn get_gpu_power(_device: &Device) -> Result<f32> {
+    bail!(Error::Unsupported("GPU power is only read on Linux".into()))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn get_gpu_clock(_device: &Device) -> Result<u32> {
+    bail!(Error::Unsupported("GPU clock is only read on Linux".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::mock::{MockTransport, Reply, SUCCESSFUL};
+
+    fn perf_state(zone: u8, perf_mode: PerfMode, fan_mode: FanMode) -> Reply {
+        Reply::Args(vec![0, zone, perf_mode.into(), fan_mode.into()])
+    }
+
+    #[test]
+    fn leaving_manual_fan_keeps_the_perf_mode_and_sends_no_rpm() {
+        let transport = MockTransport::default();
+        transport
+            .reply(perf_state(1, PerfMode::Turbo, FanMode::Manual))
+            .reply(perf_state(2, PerfMode::Turbo, FanMode::Manual));
+        set_fan_mode(&transport.device(), FanMode::Auto).unwrap();
+        let (turbo, auto) = (PerfMode::Turbo.into(), FanMode::Auto.into());
+        // the last two read the fan mode back
+        assert_eq!(
+            transport.commands(4),
+            [
+                (0x0d82, vec![0, 1, 0, 0]),
+                (0x0d82, vec![0, 2, 0, 0]),
+                (0x0d02, vec![1, 1, turbo, auto]),
+                (0x0d02, vec![1, 2, turbo, auto]),
+                (0x0d82, vec![0, 1, 0, 0]),
+                (0x0d82, vec![0, 2, 0, 0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn fan_mode_is_read_back() {
+        let transport = MockTransport::default();
+        // echoed reads are Balanced with the fan in Auto, before and after
+        let error = set_fan_mode(&transport.device(), FanMode::Manual).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::VerificationFailed { .. })
+        ));
+    }
+
+    fn command_ids(transport: &MockTransport) -> Vec<u16> {
+        transport.sent().iter().map(Packet::command).collect()
+    }
+
+    #[test]
+    fn unchanged_values_are_not_sent() {
+        let transport = MockTransport::default();
+        // the echoed getter reads as level 0
+        let sent = set_if_changed(
+            &transport.device(),
+            CpuBoost::Low,
+            get_cpu_boost,
+            set_cpu_boost,
+        );
+        assert!(!sent.unwrap());
+        assert_eq!(command_ids(&transport), [0x0d87]);
+    }
+
+    #[test]
+    fn changed_values_are_sent() {
+        let transport = MockTransport::default();
+        transport
+            .reply(Reply::Status(SUCCESSFUL))
+            .reply(perf_state(1, PerfMode::Custom, FanMode::Auto))
+            .reply(perf_state(2, PerfMode::Custom, FanMode::Auto));
+        let sent = set_if_changed(
+            &transport.device(),
+            CpuBoost::High,
+            get_cpu_boost,
+            set_cpu_boost,
+        );
+        assert!(sent.unwrap());
+        assert_eq!(command_ids(&transport), [0x0d87, 0x0d82, 0x0d82, 0x0d07]);
+    }
+}
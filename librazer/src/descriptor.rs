@@ -1,28 +1,74 @@
 use crate::feature;
+use crate::types::PerfMode;
+use std::collections::HashSet;
 
 // model_number_prefix shall conform to https://mysupport.razer.com/app/answers/detail/a_id/5481
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Descriptor {
     pub model_number_prefix: &'static str,
     pub name: &'static str,
     pub pid: u16,
     pub features: &'static [&'static str],
+    // (rows, columns) of the per-key backlight matrix
+    pub key_matrix: Option<(usize, usize)>,
+    pub perf_modes: &'static [PerfMode],
+    // highest raw (cpu, gpu) boost levels, None when the firmware only takes the named levels
+    pub boost_levels: Option<(u8, u8)>,
+    // lowest and highest RPM the firmware takes in manual fan mode
+    pub fan_rpm_limits: (u16, u16),
 }
 
+impl Descriptor {
+    /// Another model with the same feature set, e.g. a new model year or color that only
+    /// differs in model number, name and PID
+    pub const fn variant(
+        &self,
+        model_number_prefix: &'static str,
+        name: &'static str,
+        pid: u16,
+    ) -> Descriptor {
+        Descriptor {
+            model_number_prefix,
+            name,
+            pid,
+            features: self.features,
+            key_matrix: self.key_matrix,
+            perf_modes: self.perf_modes,
+            boost_levels: self.boost_levels,
+            fan_rpm_limits: self.fan_rpm_limits,
+        }
+    }
+}
+
+// Creator mode is only offered by Studio editions
+const BLADE_PERF_MODES: &[PerfMode] = &[
+    PerfMode::Balanced,
+    PerfMode::Turbo,
+    PerfMode::Silent,
+    PerfMode::Custom,
+];
+
+const BLADE_16_2023: Descriptor = Descriptor {
+    model_number_prefix: "RZ09-0483T",
+    name: "Razer Blade 16” (2023) Black",
+    pid: 0x029f,
+    features: &[
+        "battery-care",
+        "fan",
+        "kbd-backlight",
+        "kbd-rgb",
+        "lid-logo",
+        "lights-always-on",
+        "perf",
+    ],
+    key_matrix: Some((6, 16)),
+    perf_modes: BLADE_PERF_MODES,
+    boost_levels: None,
+    fan_rpm_limits: (2000, 5000),
+};
+
 pub const SUPPORTED: &[Descriptor] = &[
-    Descriptor {
-        model_number_prefix: "RZ09-0483T",
-        name: "Razer Blade 16” (2023) Black",
-        pid: 0x029f,
-        features: &[
-            "battery-care",
-            "fan",
-            "kbd-backlight",
-            "lid-logo",
-            "lights-always-on",
-            "perf",
-        ],
-    },
+    BLADE_16_2023,
     Descriptor {
         model_number_prefix: "RZ09-0482X",
         name: "Razer Blade 14” (2023) Mercury",
@@ -31,27 +77,152 @@ pub const SUPPORTED: &[Descriptor] = &[
             "battery-care",
             "fan",
             "kbd-backlight",
+            "kbd-rgb",
             "lights-always-on",
             "perf",
         ],
+        key_matrix: Some((6, 16)),
+        perf_modes: BLADE_PERF_MODES,
+        boost_levels: None,
+        fan_rpm_limits: (2000, 5000),
     },
-    Descriptor {
-        model_number_prefix: "RZ09-050",
-        name: "Razer Blade 18 (2024)",
-        pid: 0x02B8,
-        features: &[
-            "battery-care",
-            "fan",
-            "kbd-backlight",
-            "lid-logo",
-            "lights-always-on",
-            "perf",
-        ],
-    },
+    BLADE_16_2023.variant("RZ09-050", "Razer Blade 18 (2024)", 0x02B8),
 ];
 
+/// SUPPORTED with one entry per PID and feature set, the first in table order, e.g. one
+/// for model numbers that only differ in color
+pub fn all() -> Vec<&'static Descriptor> {
+    dedup(SUPPORTED)
+}
+
+fn dedup(descriptors: &'static [Descriptor]) -> Vec<&'static Descriptor> {
+    let mut seen = HashSet::new();
+    descriptors
+        .iter()
+        .filter(|d| seen.insert((d.pid, d.features)))
+        .collect()
+}
+
+/// Lowest and highest `fan_rpm_limits` of all supported models, for when the model is
+/// not known
+pub fn widest_fan_rpm_limits() -> (u16, u16) {
+    SUPPORTED
+        .iter()
+        .fold((u16::MAX, u16::MIN), |(min, max), d| {
+            (min.min(d.fan_rpm_limits.0), max.max(d.fan_rpm_limits.1))
+        })
+}
+
+/// Descriptor of a supported model by its USB PID
+pub fn for_pid(pid: u16) -> Option<&'static Descriptor> {
+    SUPPORTED.iter().find(|d| d.pid == pid)
+}
+
+/// Descriptor of a supported model by its model number, e.g. "RZ09-0483TEB3" as read by
+/// `device::read_device_model`, matched on the descriptor's prefix regardless of case
+pub fn match_model(model: &str) -> Option<&'static Descriptor> {
+    SUPPORTED.iter().find(|d| {
+        model
+            .get(..d.model_number_prefix.len())
+            .is_some_and(|head| head.eq_ignore_ascii_case(d.model_number_prefix))
+    })
+}
+
+/// Whether `match_model` knows the model number
+pub fn is_supported(model: &str) -> bool {
+    match_model(model).is_some()
+}
+
+/// Features a supported PID offers, e.g. for a front end rendering controls per device
+pub fn features_for_pid(pid: u16) -> Option<&'static [&'static str]> {
+    for_pid(pid).map(|d| d.features)
+}
+
+// Matches a USB product string such as "Razer Blade 16" against the model names,
+// only when it identifies a single model
+pub fn find_by_name(name: &str) -> Option<&'static Descriptor> {
+    let name = name.trim().to_lowercase();
+    if name.is_empty() {
+        return None;
+    }
+    let mut found = SUPPORTED
+        .iter()
+        .filter(|d| d.name.to_lowercase().starts_with(&name));
+    match (found.next(), found.next()) {
+        (Some(descriptor), None) => Some(descriptor),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeviceClass {
+    Laptop,
+    Dock,
+    // peripherals and laptops missing from SUPPORTED
+    Other,
+}
+
+// Docks and eGPU enclosures exposing a HID interface, PIDs as listed by openrazer
+const DOCKS: &[(u16, &str)] = &[(0x0215, "Razer Core"), (0x0f1a, "Razer Core X Chroma")];
+
+pub fn classify(pid: u16) -> (DeviceClass, Option<&'static str>) {
+    if let Some(descriptor) = for_pid(pid) {
+        (DeviceClass::Laptop, Some(descriptor.name))
+    } else if let Some((_, name)) = DOCKS.iter().find(|(dock, _)| *dock == pid) {
+        (DeviceClass::Dock, Some(name))
+    } else {
+        (DeviceClass::Other, None)
+    }
+}
+
 const _VALIDATE_FEATURES: () = {
     crate::const_for! { device in SUPPORTED => {
         feature::validate_features(device.features);
     }}
 };
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_model_by_prefix() {
+        for (model, name) in [
+            ("RZ09-0483TEB3", Some("Razer Blade 16” (2023) Black")),
+            ("RZ09-0483T", Some("Razer Blade 16” (2023) Black")),
+            ("RZ09-0482XEH3", Some("Razer Blade 14” (2023) Mercury")),
+            ("RZ09-0509TEB3", Some("Razer Blade 18 (2024)")),
+            ("rz09-0483teb3", Some("Razer Blade 16” (2023) Black")),
+            ("RZ09-0483", None),
+            ("RZ09-0421NEH3", None),
+            (" RZ09-0483TEB3", None),
+            ("To be filled by O.E.M.", None),
+            ("", None),
+            ("RZ09-048é", None),
+        ] {
+            assert_eq!(match_model(model).map(|d| d.name), name, "{:?}", model);
+            assert_eq!(is_supported(model), name.is_some(), "{:?}", model);
+        }
+    }
+
+    #[test]
+    fn every_supported_prefix_matches_itself() {
+        for descriptor in SUPPORTED {
+            assert!(is_supported(descriptor.model_number_prefix));
+        }
+    }
+
+    #[test]
+    fn all_collapses_entries_with_one_pid_and_feature_set() {
+        const TABLE: &[Descriptor] = &[
+            BLADE_16_2023,
+            BLADE_16_2023.variant("RZ09-0483S", "Razer Blade 16” (2023) Mercury", 0x029f),
+            BLADE_16_2023.variant("RZ09-050", "Razer Blade 18 (2024)", 0x02b8),
+        ];
+        let names: Vec<&str> = dedup(TABLE).iter().map(|d| d.name).collect();
+        assert_eq!(
+            names,
+            ["Razer Blade 16” (2023) Black", "Razer Blade 18 (2024)"]
+        );
+    }
+}
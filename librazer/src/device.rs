@@ -1,19 +1,72 @@
-use crate::descriptor::{Descriptor, SUPPORTED};
+use crate::descriptor::{self, Descriptor, DeviceClass};
 use crate::packet::Packet;
+use crate::transport::{Bus, Transport};
+use crate::Error;
 
 use anyhow::{anyhow, Context, Result};
-use log::{debug};
-use std::{thread, time};
+use log::{debug, warn};
+#[cfg(target_os = "linux")]
 use std::fs;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::{thread, time};
+
+/// Tuning for how a `Device` talks to the controller.
+#[derive(Clone, Debug)]
+pub struct DeviceConfig {
+    /// Upper bound for a whole `send`, after which it fails with `Error::Timeout`
+    pub timeout: time::Duration,
+    /// Shrink the wait before the first response read while the device answers in time,
+    /// growing it again when it doesn't. Helps loops sending many commands.
+    pub adaptive_timing: bool,
+    /// Skip the pause before writing and read the response without waiting, for measuring
+    /// raw HID throughput. Slow controllers may then fail commands.
+    pub no_delays: bool,
+    /// Pause before writing a report, giving the controller time after the previous one
+    pub pre_write_delay: time::Duration,
+    /// Wait after writing before the first response read, where adaptive timing starts from
+    pub post_write_delay: time::Duration,
+    /// Refuse commands that change settings with `Error::Unsupported`, only sending those
+    /// `Packet::is_read` classifies as getters. For monitoring without write access.
+    pub read_only: bool,
+    /// Times a command that timed out or got a malformed response is sent again before
+    /// failing. Setters are sent again too, they set the same value.
+    pub retries: u32,
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        Self {
+            timeout: time::Duration::from_millis(500),
+            adaptive_timing: false,
+            no_delays: false,
+            pre_write_delay: time::Duration::from_millis(1),
+            post_write_delay: Device::RESPONSE_POLL_INTERVAL,
+            read_only: false,
+            retries: 0,
+        }
+    }
+}
 
 pub struct Device {
-    device: hidapi::HidDevice,
+    // shared with the thread doing the exchange, which may outlive a timed out send
+    device: Arc<Mutex<Box<dyn Transport>>>,
+    // where `send` finds the device again after it went away
+    bus: Arc<dyn Bus>,
+    // opened again by `send` after the device went away, see RECONNECT_ERRORS. Replaced
+    // when a rescan no longer lists it, e.g. after an undock.
+    path: Mutex<std::ffi::CString>,
     pub info: Descriptor,
+    config: DeviceConfig,
+    // wait before the first response read in µs, tuned by adaptive timing
+    response_delay: Arc<AtomicU64>,
+    // pending responses read again, for `retries`
+    retries: Arc<AtomicU64>,
 }
 
 // Read the model id and clip to conform with https://mysupport.razer.com/app/answers/detail/a_id/5481
 #[cfg(target_os = "windows")]
-fn read_device_model() -> Result<String> {
+pub fn read_device_model() -> Result<String> {
     let hklm = winreg::RegKey::predef(winreg::enums::HKEY_LOCAL_MACHINE);
     let bios = hklm.open_subkey("HARDWARE\\DESCRIPTION\\System\\BIOS")?;
     let system_sku: String = bios.get_value("SystemSKU")?;
@@ -21,20 +74,12 @@ fn read_device_model() -> Result<String> {
 }
 
 #[cfg(target_os = "linux")]
-fn read_device_model() -> Result<String> {
+pub fn read_device_model() -> Result<String> {
     let path = "/sys/devices/virtual/dmi/id/product_sku";
     
     match fs::read_to_string(path) {
-        Ok(sku) => {
-            let sku = sku.trim().to_string();
-            
-            if sku.starts_with("RZ") {
-                Ok(sku)
-            } else {
-                debug!("Invalid Razer SKU prefix: {}", sku);
-                Err(anyhow!("Invalid Razer SKU: {}", sku))
-            }
-        }
+        // the RZ09- prefix is checked by the caller, which can fall back to the HID devices
+        Ok(sku) => Ok(sku.trim().to_string()),
         Err(e) => {
             debug!("Failed to read {}: {}", path, e);
             Err(anyhow!("DMI read error: {}", e))
@@ -43,70 +88,561 @@ fn read_device_model() -> Result<String> {
 }
 
 #[cfg(not(any(target_os = "windows", target_os = "linux")))]
-fn read_device_model() -> Result<String> {
+pub fn read_device_model() -> Result<String> {
     debug!("Unsupported platform detected");
     anyhow::bail!("Automatic model detection is not implemented for this platform")
 }
 
+// Scanning HID devices can take hundreds of ms, so detect, enumerate and new share a
+// single scan per process. Devices plugged in afterwards are not seen.
+fn hid_api() -> Result<std::sync::MutexGuard<'static, hidapi::HidApi>> {
+    static API: std::sync::OnceLock<std::sync::Mutex<hidapi::HidApi>> = std::sync::OnceLock::new();
+    if API.get().is_none() {
+        let api = hidapi::HidApi::new().context("Failed to create hid api")?;
+        // a concurrent caller may have won the race, either scan is fine
+        let _ = API.set(std::sync::Mutex::new(api));
+    }
+    Ok(API
+        .get()
+        .unwrap()
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner))
+}
+
+// On Windows a device held by Synapse fails to open or ignores the probe, with nothing
+// in the error pointing at Synapse
+#[cfg(target_os = "windows")]
+const HELD_BY_SYNAPSE_HINT: &str =
+    "Razer Synapse may be holding the device, close it or stop the Razer Synapse Service";
+
+// OS errors of a handle whose device went away under it, e.g. while resuming from USB
+// autosuspend. hidapi only passes them on as text.
+const RECONNECT_ERRORS: &[&str] = &[
+    "No such device",
+    "Broken pipe",
+    "Input/output error",
+    "device is not connected",
+];
+
+fn needs_reconnect(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|e| e.downcast_ref::<hidapi::HidError>())
+        .any(|e| {
+            let message = e.to_string();
+            RECONNECT_ERRORS.iter().any(|m| message.contains(m))
+        })
+}
+
+// Failures that sending the same report again may get past
+fn is_transient(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|e| e.downcast_ref::<Error>())
+        .any(|e| matches!(e, Error::Timeout(_) | Error::Protocol(_)))
+}
+
+// hidapi's device list
+struct HidBus;
+
+impl Bus for HidBus {
+    fn rescan(&self) -> Result<()> {
+        Device::rescan_pids().map(|_| ())
+    }
+
+    fn interface_paths(&self, pid: u16) -> Result<Vec<std::ffi::CString>> {
+        Ok(hid_api()?
+            .device_list()
+            .filter(|info| (info.vendor_id(), info.product_id()) == (Device::RAZER_VID, pid))
+            .map(|info| info.path().to_owned())
+            .collect())
+    }
+
+    fn open(&self, path: &std::ffi::CStr) -> Result<Box<dyn Transport>> {
+        let device = hid_api()?
+            .open_path(path)
+            .map_err(|e| open_error(path, e))?;
+        Ok(Box::new(device))
+    }
+}
+
+fn open_error(path: &std::ffi::CStr, error: hidapi::HidError) -> anyhow::Error {
+    let message = format!("Failed to open {:?}: {}", path, error);
+    // hidapi only reports the OS error as text
+    #[cfg(target_os = "windows")]
+    if message.contains("Access is denied") || message.contains("being used by another process") {
+        return Error::PermissionDenied(format!("{}. {}", message, HELD_BY_SYNAPSE_HINT)).into();
+    }
+    if message.contains("Permission denied") || message.contains("Access is denied") {
+        Error::PermissionDenied(message).into()
+    } else {
+        anyhow!(message)
+    }
+}
+
 impl Device {
     const RAZER_VID: u16 = 0x1532;
+    // The response is read early and re-read while it is pending, instead of
+    // waiting a fixed 2ms that most commands do not need. Default post write delay.
+    const RESPONSE_POLL_INTERVAL: time::Duration = time::Duration::from_micros(500);
+    // a busy device backs off up to this interval, failures are reported immediately
+    const BUSY_POLL_INTERVAL_MAX: time::Duration = time::Duration::from_millis(8);
+    const RESPONSE_DEADLINE: time::Duration = time::Duration::from_millis(50);
+    const WAIT_READY_INTERVAL: time::Duration = time::Duration::from_millis(50);
+    // bounds of the adaptive first read delay
+    const ADAPTIVE_DELAY_FLOOR: time::Duration = time::Duration::from_micros(100);
+    const ADAPTIVE_DELAY_MAX: time::Duration = time::Duration::from_millis(2);
 
     pub fn info(&self) -> &Descriptor {
         &self.info
     }
 
+    pub fn config(&self) -> &DeviceConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: DeviceConfig) {
+        self.response_delay.store(
+            config.post_write_delay.as_micros() as u64,
+            Ordering::Relaxed,
+        );
+        self.config = config;
+    }
+
+    /// Wait before the first response read that the next command uses, as tuned by
+    /// adaptive timing from `DeviceConfig::post_write_delay`.
+    pub fn response_delay(&self) -> time::Duration {
+        time::Duration::from_micros(self.response_delay.load(Ordering::Relaxed))
+    }
+
+    /// Number of times a response was still pending and had to be read again, since the
+    /// device was opened. A high rate per command suggests a longer post write delay.
+    pub fn retries(&self) -> u64 {
+        self.retries.load(Ordering::Relaxed)
+    }
+
     pub fn new(descriptor: Descriptor) -> Result<Device> {
-        let api = hidapi::HidApi::new().context("Failed to create hid api")?;
+        let paths = HidBus.interface_paths(descriptor.pid)?;
+
+        let mut rejected_probe = Vec::new();
+        for path in &paths {
+            let device = Device::open(path, descriptor.clone())?;
+            if device.lock().send_feature_report(&[0, 0]).is_ok() {
+                return Ok(device);
+            }
+            debug!("{:?} rejected the probe report", path);
+            rejected_probe.push(path);
+        }
+
+        // Some control interfaces reject the two-byte probe but accept real packets,
+        // so fall back to the first one answering a harmless getter
+        if let Some(device) = rejected_probe
+            .into_iter()
+            .find_map(|path| Device::from_path(path, descriptor.clone()).ok())
+        {
+            debug!("Using the interface that answered the performance mode getter");
+            return Ok(device);
+        }
+
+        #[cfg(target_os = "windows")]
+        if !paths.is_empty() {
+            anyhow::bail!(Error::DeviceNotFound(format!(
+                "No interface of {:?} responded. {}",
+                descriptor, HELD_BY_SYNAPSE_HINT
+            )));
+        }
+        anyhow::bail!(Error::DeviceNotFound(format!(
+            "Failed to open device {:?}",
+            descriptor
+        )))
+    }
+
+    /// Opens the same model again with the same configuration, after rescanning the HID
+    /// devices, e.g. when the device went away during sleep or a replug.
+    pub fn reopen(&self) -> Result<Device> {
+        Device::rescan_pids()?;
+        let mut device = Device::new(self.info.clone())?;
+        device.set_config(self.config.clone());
+        Ok(device)
+    }
+
+    /// Opens a specific HID interface, e.g. when `new` picks the wrong one.
+    /// Fails unless the interface answers the performance mode getter.
+    pub fn from_path(path: &std::ffi::CStr, descriptor: Descriptor) -> Result<Device> {
+        let device = Device::open(path, descriptor)?;
+        device
+            .probe()
+            .with_context(|| format!("{:?} did not answer the performance mode getter", path))?;
+        Ok(device)
+    }
 
-        for info in api.device_list().filter(|info| {
-            (info.vendor_id(), info.product_id()) == (Device::RAZER_VID, descriptor.pid)
-        }) {
-            let path = info.path();
-            let device = api.open_path(path)?;
-            if device.send_feature_report(&[0, 0]).is_ok() {
-                return Ok(Device {
-                    device,
-                    info: descriptor.clone(),
-                });
+    // a harmless getter every supported controller answers
+    fn probe(&self) -> Result<Packet> {
+        self.send(Packet::new(0x0d82, &[0, 1, 0, 0]))
+    }
+
+    /// Polls the performance mode getter until the device answers, e.g. right after a
+    /// hotplug when the controller needs a moment before it takes commands.
+    pub fn wait_ready(&self, timeout: time::Duration) -> Result<()> {
+        let started = time::Instant::now();
+        loop {
+            match self.probe() {
+                Ok(_) => {
+                    debug!("Device ready after {:.1?}", started.elapsed());
+                    return Ok(());
+                }
+                Err(e) if started.elapsed() >= timeout => {
+                    anyhow::bail!(Error::Timeout(format!(
+                        "Device not ready after {:?}: {:#}",
+                        timeout, e
+                    )))
+                }
+                Err(e) => {
+                    debug!("Device not ready yet: {:#}", e);
+                    thread::sleep(Device::WAIT_READY_INTERVAL);
+                }
             }
         }
-        anyhow::bail!("Failed to open device {:?}", descriptor)
     }
 
+    fn open(path: &std::ffi::CStr, descriptor: Descriptor) -> Result<Device> {
+        Ok(Device::from_transport(
+            HidBus.open(path)?,
+            Arc::new(HidBus),
+            path,
+            descriptor,
+        ))
+    }
+
+    pub(crate) fn from_transport(
+        transport: Box<dyn Transport>,
+        bus: Arc<dyn Bus>,
+        path: &std::ffi::CStr,
+        descriptor: Descriptor,
+    ) -> Device {
+        Device {
+            device: Arc::new(Mutex::new(transport)),
+            bus,
+            path: Mutex::new(path.to_owned()),
+            info: descriptor,
+            config: DeviceConfig::default(),
+            response_delay: Arc::new(AtomicU64::new(
+                Device::RESPONSE_POLL_INTERVAL.as_micros() as u64
+            )),
+            retries: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Box<dyn Transport>> {
+        self.device
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Sends the report and waits for the response, failing with `Error::Timeout` when
+    /// the device doesn't answer within `DeviceConfig::timeout`. When the handle reports
+    /// that the device went away, e.g. the first command after USB autosuspend, the HID
+    /// path is opened again and the report sent once more before failing. Timeouts and
+    /// protocol errors are retried `DeviceConfig::retries` times.
     pub fn send(&self, report: Packet) -> Result<Packet> {
+        anyhow::ensure!(
+            !self.config.read_only || report.is_read(),
+            Error::Unsupported(format!(
+                "Command {:#06x} changes settings, refused in read-only mode",
+                report.command()
+            ))
+        );
+        let mut attempt = 0;
+        loop {
+            let response = match self.send_once(report.clone()) {
+                Err(e) if needs_reconnect(&e) => {
+                    debug!("{:#}, rescanning and retrying", e);
+                    self.reconnect()?;
+                    self.send_once(report.clone())
+                }
+                response => response,
+            };
+            match response {
+                Err(e) if attempt < self.config.retries && is_transient(&e) => {
+                    attempt += 1;
+                    debug!(
+                        "{:#}, sending again ({}/{})",
+                        e, attempt, self.config.retries
+                    );
+                }
+                response => return response,
+            }
+        }
+    }
+
+    // Swaps in a new handle, the old one is dropped. The HID devices are scanned again
+    // first: the cached list may still hold a path that went away, while the device is
+    // back under another one, e.g. after an undock and redock.
+    fn reconnect(&self) -> Result<()> {
+        self.bus.rescan()?;
+        let paths = self.bus.interface_paths(self.info.pid)?;
+        let mut path = self
+            .path
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if paths.contains(&path) {
+            *self.lock() = self.bus.open(&path)?;
+            return Ok(());
+        }
+        // the interface taking the probe report, as `new` picks it
+        let Some((new_path, device)) = paths.into_iter().find_map(|candidate| {
+            let device = self.bus.open(&candidate).ok()?;
+            device
+                .send_feature_report(&[0, 0])
+                .is_ok()
+                .then_some((candidate, device))
+        }) else {
+            anyhow::bail!(Error::DeviceNotFound(format!(
+                "{} went away from {:?} and is not back after a rescan",
+                self.info.name, path
+            )));
+        };
+        warn!(
+            "{:?} is stale after a rescan, {} is now at {:?}",
+            path, self.info.name, new_path
+        );
+        *self.lock() = device;
+        *path = new_path;
+        Ok(())
+    }
+
+    fn send_once(&self, report: Packet) -> Result<Packet> {
+        let command = format!("command {:#06x}", report.command());
+        let config = self.config.clone();
+        // nothing to tune without delays
+        let response_delay =
+            (config.adaptive_timing && !config.no_delays).then(|| Arc::clone(&self.response_delay));
+        let retries = Arc::clone(&self.retries);
+        self.with_timeout(command, move |device| {
+            Device::exchange(device, report, &config, response_delay.as_deref(), &retries)
+        })
+    }
+
+    /// EXPERIMENTAL, for reverse engineering layouts that don't fit `Packet`: sends `data`
+    /// as is after the report id and returns the bytes read back, without checking the
+    /// size, status, checksum or that they answer `data` at all. Nothing stops this from
+    /// sending a command that bricks the firmware.
+    #[cfg(feature = "experimental-raw")]
+    pub fn send_raw(&self, data: &[u8]) -> Result<Vec<u8>> {
+        // raw layouts can't be classified
+        anyhow::ensure!(
+            !self.config.read_only,
+            Error::Unsupported("Raw reports are refused in read-only mode".into())
+        );
+        let report: Vec<u8> = [0_u8].iter().chain(data).copied().collect();
+        let config = self.config.clone();
+        self.with_timeout("raw report".to_string(), move |device| {
+            device
+                .send_feature_report(&report)
+                .context("Failed to send feature report")?;
+            if !config.no_delays {
+                thread::sleep(config.post_write_delay);
+            }
+            let mut response_buf = vec![0x00; 1 + std::mem::size_of::<Packet>()];
+            let size = match device.get_feature_report(&mut response_buf)? {
+                0 => response_buf.len(),
+                size => size,
+            };
+            debug!(
+                "raw {:02x?} -> {:02x?}",
+                &report[1..],
+                &response_buf[1..size]
+            );
+            Ok(response_buf[1..size].to_vec())
+        })
+    }
+
+    // hidapi calls can't be interrupted, so `exchange` runs on a worker and a wedged
+    // controller only blocks that
+    fn with_timeout<T: Send + 'static>(
+        &self,
+        what: String,
+        exchange: impl FnOnce(&dyn Transport) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let (tx, rx) = mpsc::channel();
+        let device = Arc::clone(&self.device);
+        thread::spawn(move || {
+            let device = device
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            let _ = tx.send(exchange(device.as_ref()));
+        });
+
+        match rx.recv_timeout(self.config.timeout) {
+            Ok(response) => response,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                anyhow::bail!(Error::Timeout(format!(
+                    "Device did not answer {} within {:?}",
+                    what, self.config.timeout
+                )))
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                anyhow::bail!("HID exchange for {} panicked", what)
+            }
+        }
+    }
+
+    // `response_delay` is given with adaptive timing, and updated from how this exchange went
+    fn exchange(
+        device: &dyn Transport,
+        report: Packet,
+        config: &DeviceConfig,
+        response_delay: Option<&AtomicU64>,
+        retry_count: &AtomicU64,
+    ) -> Result<Packet> {
+        let delay = response_delay.map(|d| time::Duration::from_micros(d.load(Ordering::Relaxed)));
+        let adapt = |next: time::Duration| {
+            if let Some(d) = response_delay {
+                let next = next.clamp(Device::ADAPTIVE_DELAY_FLOOR, Device::ADAPTIVE_DELAY_MAX);
+                d.store(next.as_micros() as u64, Ordering::Relaxed);
+            }
+        };
+
         // extra byte for report id
         let mut response_buf: Vec<u8> = vec![0x00; 1 + std::mem::size_of::<Packet>()];
 
-        thread::sleep(time::Duration::from_micros(1000));
-        self.device
+        if !config.no_delays {
+            thread::sleep(config.pre_write_delay);
+        }
+        let started = time::Instant::now();
+        device
             .send_feature_report(
                 [0_u8; 1] // report id
                     .iter()
                     .copied()
-                    .chain(Into::<Vec<u8>>::into(&report).into_iter())
+                    .chain(Into::<Vec<u8>>::into(&report))
                     .collect::<Vec<_>>()
                     .as_slice(),
             )
             .context("Failed to send feature report")?;
 
-        thread::sleep(time::Duration::from_micros(2000));
-        if response_buf.len() != self.device.get_feature_report(&mut response_buf)? {
-            return Err(anyhow!("Response size != {}", response_buf.len()));
+        let sent = time::Instant::now();
+        let mut retries = 0;
+        let mut poll_interval = match config.no_delays {
+            true => time::Duration::ZERO,
+            false => delay.unwrap_or(config.post_write_delay),
+        };
+        loop {
+            thread::sleep(poll_interval);
+            match device.get_feature_report(&mut response_buf)? {
+                size if size == response_buf.len() => (),
+                // some backends fill the buffer but report 0 bytes
+                0 => debug!("Feature report returned 0 bytes, assuming a full response"),
+                size => {
+                    // likely read too early, back off for the next command
+                    adapt(poll_interval * 2);
+                    anyhow::bail!(Error::Protocol(format!(
+                        "Response size {} != {}",
+                        size,
+                        response_buf.len()
+                    )))
+                }
+            }
+
+            // skip report id byte
+            let response = <&[u8] as TryInto<Packet>>::try_into(&response_buf[1..])?;
+            if response.is_pending(&report) && sent.elapsed() < Device::RESPONSE_DEADLINE {
+                if response.is_busy() {
+                    poll_interval = (poll_interval * 2).min(Device::BUSY_POLL_INTERVAL_MAX);
+                }
+                retries += 1;
+                retry_count.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            debug!(
+                "send {:#06x} in {:.1?} ({} retries)",
+                report.command(),
+                started.elapsed(),
+                retries
+            );
+            if let Some(delay) = delay {
+                // answered on the first read: try reading sooner, otherwise wait longer, up to
+                // the configured delay unless a size mismatch already raised it
+                let next = match retries {
+                    0 => delay * 3 / 4,
+                    _ => (delay * 2).min(config.post_write_delay.max(delay)),
+                };
+                adapt(next);
+                debug!("first read delay {:.1?} -> {:.1?}", delay, next);
+            }
+            return response.ensure_matches_report(&report);
         }
+    }
+
+    /// Opens every connected device whose PID has a descriptor, without reading the model.
+    /// Each open can fail on its own, e.g. when another process holds the device.
+    pub fn list() -> Result<Vec<(Descriptor, Result<Device>)>> {
+        Ok(Device::list_pids()?
+            .into_iter()
+            .filter_map(descriptor::for_pid)
+            .map(|descriptor| (descriptor.clone(), Device::new(descriptor.clone())))
+            .collect())
+    }
+
+    // PIDs of all connected Razer HID devices, without looking at the model
+    pub fn list_pids() -> Result<Vec<u16>> {
+        Device::pids(&*hid_api()?)
+    }
 
-        // skip report id byte
-        let response = <&[u8] as TryInto<Packet>>::try_into(&response_buf[1..])?;
-        response.ensure_matches_report(&report)
+    // Like list_pids, but rescans first so devices plugged in since the first scan are seen
+    pub(crate) fn rescan_pids() -> Result<Vec<u16>> {
+        let mut api = hid_api()?;
+        api.refresh_devices()
+            .context("Failed to rescan hid devices")?;
+        Device::pids(&api)
+    }
+
+    fn pids(api: &hidapi::HidApi) -> Result<Vec<u16>> {
+        Ok(api
+            .device_list()
+            .filter(|info| info.vendor_id() == Device::RAZER_VID)
+            .map(|info| info.product_id())
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .collect())
+    }
+
+    /// Model number of this machine, whether or not it is supported. Reads DMI or the
+    /// registry, falling back to the product string of a supported Razer interface.
+    pub fn model() -> Result<String> {
+        let api = hid_api()?;
+        Device::detect_model(
+            api.device_list()
+                .filter(|info| info.vendor_id() == Device::RAZER_VID),
+        )
+    }
+
+    fn detect_model<'a>(
+        mut razer_devices: impl Iterator<Item = &'a hidapi::DeviceInfo>,
+    ) -> Result<String> {
+        match read_device_model() {
+            Ok(m) => Ok(m),
+            Err(e) => match razer_devices.find_map(|info| {
+                descriptor::find_by_name(info.product_string()?)
+                    .filter(|d| d.pid == info.product_id())
+            }) {
+                Some(d) => {
+                    debug!(
+                        "Failed to read model ({}), matched {} by product string",
+                        e, d.name
+                    );
+                    Ok(d.model_number_prefix.to_string())
+                }
+                None => {
+                    debug!("Failed to detect model: {}", e);
+                    Err(anyhow!("Failed to detect model: {}", e))
+                }
+            },
+        }
     }
 
     pub fn enumerate() -> Result<(Vec<u16>, String)> {
-        let api = match hidapi::HidApi::new() {
-            Ok(api) => api,
-            Err(e) => {
-                debug!("Failed to create HID API: {}", e);
-                return Err(anyhow!("HID API error: {}", e));
-            }
-        };
+        let api = hid_api()?;
 
         let devices = api.device_list().collect::<Vec<_>>();
         
@@ -117,42 +653,68 @@ impl Device {
         
         if razer_devices.is_empty() {
             debug!("No Razer devices found");
-            return Err(anyhow!("No Razer devices found"));
+            anyhow::bail!(Error::DeviceNotFound("No Razer devices found".into()));
         }
 
         // Extract unique PIDs
         let pids: Vec<u16> = razer_devices.iter()
             .map(|info| info.product_id())
-            .collect::<std::collections::HashSet<_>>()
+            .collect::<std::collections::BTreeSet<_>>()
             .into_iter()
             .collect();
 
-        // Get device model
-        let model = match read_device_model() {
-            Ok(m) => m,
-            Err(e) => {
-                debug!("Failed to detect model: {}", e);
-                return Err(anyhow!("Failed to detect model: {}", e));
-            }
-        };
+        let model = Device::detect_model(razer_devices.iter().copied().copied())?;
         
         if !model.starts_with("RZ09-") {
-            debug!("Detected model is not a Razer laptop: {}", model);
-            return Err(anyhow!("Detected model is not a Razer laptop: {}", model));
+            // OEM firmware may report another SKU on a real Blade, so only dead-end
+            // when no laptop interface is present either
+            let Some(laptop_pid) = pids
+                .iter()
+                .find(|&&pid| descriptor::classify(pid).0 == DeviceClass::Laptop)
+            else {
+                debug!("Detected model is not a Razer laptop: {}", model);
+                anyhow::bail!(Error::DeviceNotFound(format!(
+                    "Detected model is not a Razer laptop: {}",
+                    model
+                )));
+            };
+            warn!(
+                "Model {:?} is not a Razer laptop SKU, but laptop PID {:#06x} is present. Use `manual --pid {:#06x}` to control it",
+                model, laptop_pid, laptop_pid
+            );
         }
 
         Ok((pids, model))
     }
     pub fn detect() -> Result<Device> {
-        let (pid_list, model_number_prefix) = Device::enumerate()?;
+        Device::detect_checked(false)
+    }
 
-        // Find matching descriptor
-        let supported = SUPPORTED.iter().find(|d| 
-            model_number_prefix.starts_with(d.model_number_prefix)
-        );
+    /// Like `detect`, but fails instead of warning when the PID of the model's descriptor
+    /// is not among the enumerated ones, e.g. when DMI and USB disagree after a firmware update
+    pub fn detect_strict() -> Result<Device> {
+        Device::detect_checked(true)
+    }
 
-        match supported {
+    fn detect_checked(strict: bool) -> Result<Device> {
+        let (pid_list, model_number_prefix) = Device::enumerate()?;
+
+        match descriptor::match_model(&model_number_prefix) {
             Some(desc) => {
+                if !pid_list.contains(&desc.pid) {
+                    let message = format!(
+                        "Model {} should have PID {:#06x}, but the present PIDs are [{}]. Use `auto --pid <PID>` to pick one",
+                        model_number_prefix,
+                        desc.pid,
+                        pid_list
+                            .iter()
+                            .map(|pid| format!("{:#06x}", pid))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                    anyhow::ensure!(!strict, Error::DeviceNotFound(message));
+                    warn!("{}", message);
+                }
                 Device::new(desc.clone())
             }
             None => {
@@ -161,12 +723,157 @@ impl Device {
                     .collect::<Vec<_>>()
                     .join(", ");
                 
-                Err(anyhow!(
-                    "Model {} with PIDs [{}] is not supported",
-                    model_number_prefix,
-                    pids_fmt
+                Err(Error::Unsupported(format!(
+                    "Model {} with PIDs [{}] is not supported, try `manual --pid <PID>`",
+                    model_number_prefix, pids_fmt
                 ))
+                .into())
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::mock::{MockBus, MockTransport, Reply, BUSY, FAILURE, NOT_SUPPORTED};
+
+    fn perf_getter() -> Packet {
+        Packet::new(0x0d82, &[0, 1, 0, 0])
+    }
+
+    fn error_of<T: std::fmt::Debug>(result: Result<T>) -> Error {
+        let error = result.unwrap_err();
+        error
+            .chain()
+            .find_map(|e| e.downcast_ref::<Error>())
+            .cloned()
+            .unwrap_or_else(|| panic!("not a librazer::Error: {:#}", error))
+    }
+
+    #[test]
+    fn busy_responses_are_read_again() {
+        let transport = MockTransport::default();
+        transport
+            .reply(Reply::Status(BUSY))
+            .reply(Reply::Status(BUSY));
+        let device = transport.device();
+        device.send(perf_getter()).unwrap();
+        assert_eq!(device.retries(), 2);
+        // waited for, not sent again
+        assert_eq!(transport.sent().len(), 1);
+    }
+
+    #[test]
+    fn staying_busy_fails_after_the_deadline() {
+        let transport = MockTransport::default();
+        transport.answer_with(BUSY);
+        let message = error_of(transport.device().send(perf_getter())).to_string();
+        assert!(message.contains("stayed busy"), "{}", message);
+    }
+
+    #[test]
+    fn failure_statuses_are_named() {
+        let transport = MockTransport::default();
+        transport.reply(Reply::Status(NOT_SUPPORTED));
+        let device = transport.device();
+        assert!(matches!(
+            error_of(device.send(perf_getter())),
+            Error::Unsupported(_)
+        ));
+        transport.reply(Reply::Status(FAILURE));
+        assert!(matches!(
+            error_of(device.send(perf_getter())),
+            Error::Protocol(message) if message == "Command failed"
+        ));
+    }
+
+    #[test]
+    fn zero_length_responses_are_read_as_full() {
+        let transport = MockTransport::default();
+        transport.reply(Reply::Size(0));
+        let response = transport.device().send(perf_getter()).unwrap();
+        assert_eq!(response.command(), 0x0d82);
+    }
+
+    #[test]
+    fn short_responses_are_rejected() {
+        let transport = MockTransport::default();
+        transport.reply(Reply::Size(10));
+        assert!(matches!(
+            error_of(transport.device().send(perf_getter())),
+            Error::Protocol(message) if message.starts_with("Response size 10")
+        ));
+    }
+
+    #[test]
+    fn read_only_refuses_writes_before_sending() {
+        let transport = MockTransport::default();
+        let mut device = transport.device();
+        device.set_config(DeviceConfig {
+            read_only: true,
+            no_delays: true,
+            ..DeviceConfig::default()
+        });
+        assert!(matches!(
+            error_of(device.send(Packet::new(0x0d02, &[1, 1, 0, 0]))),
+            Error::Unsupported(_)
+        ));
+        assert!(transport.sent().is_empty());
+        device.send(perf_getter()).unwrap();
+        assert_eq!(transport.sent().len(), 1);
+    }
+
+    #[test]
+    fn reconnects_to_the_same_path() {
+        let (old, new) = (MockTransport::default(), MockTransport::default());
+        old.go_away();
+        let bus = MockBus::new(vec![("a", old.clone())], vec![("a", new.clone())]);
+        let device = old.device_on(bus, "a");
+        device.send(perf_getter()).unwrap();
+        assert_eq!(new.sent().len(), 1);
+        assert_eq!(device.path.lock().unwrap().to_str(), Ok("a"));
+    }
+
+    #[test]
+    fn rescan_finds_the_device_under_another_path() {
+        let (old, new) = (MockTransport::default(), MockTransport::default());
+        old.go_away();
+        // "a" lingers in the list until the rescan
+        let bus = MockBus::new(vec![("a", old.clone())], vec![("b", new.clone())]);
+        let device = old.device_on(bus, "a");
+        device.send(perf_getter()).unwrap();
+        assert_eq!(new.sent().len(), 1);
+        assert_eq!(device.path.lock().unwrap().to_str(), Ok("b"));
+    }
+
+    #[test]
+    fn gone_for_good_is_device_not_found() {
+        let old = MockTransport::default();
+        old.go_away();
+        let bus = MockBus::new(vec![("a", old.clone())], vec![]);
+        let device = old.device_on(bus, "a");
+        assert!(matches!(
+            error_of(device.send(perf_getter())),
+            Error::DeviceNotFound(_)
+        ));
+    }
+
+    #[test]
+    fn malformed_responses_are_sent_again_with_retries() {
+        let transport = MockTransport::default();
+        transport.reply(Reply::Size(10));
+        let mut device = transport.device();
+        device.set_config(DeviceConfig {
+            retries: 1,
+            no_delays: true,
+            ..DeviceConfig::default()
+        });
+        device.send(perf_getter()).unwrap();
+        assert_eq!(transport.sent().len(), 2);
+        // not retried
+        transport.reply(Reply::Status(NOT_SUPPORTED));
+        assert!(device.send(perf_getter()).is_err());
+        assert_eq!(transport.sent().len(), 3);
+    }
+}
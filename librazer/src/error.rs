@@ -0,0 +1,34 @@
+use std::fmt;
+
+/// Failure categories callers may want to tell apart, e.g. to pick an exit code.
+/// Functions still return `anyhow::Result`; use `downcast_ref::<Error>()` on the error chain.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    DeviceNotFound(String),
+    PermissionDenied(String),
+    Unsupported(String),
+    Protocol(String),
+    // the device didn't answer in time, e.g. a wedged controller
+    Timeout(String),
+    // the device accepted a setting but reads back something else
+    VerificationFailed { expected: String, actual: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::DeviceNotFound(message)
+            | Error::PermissionDenied(message)
+            | Error::Unsupported(message)
+            | Error::Protocol(message)
+            | Error::Timeout(message) => write!(f, "{}", message),
+            Error::VerificationFailed { expected, actual } => write!(
+                f,
+                "Device reports {} after setting {}, the setting was not applied",
+                actual, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,89 @@
+use crate::descriptor::{self, Descriptor};
+use crate::device::Device;
+
+use anyhow::Result;
+use std::collections::{BTreeSet, VecDeque};
+use std::sync::mpsc;
+use std::{thread, time};
+
+/// Change in the set of connected supported devices
+#[derive(Debug, Clone)]
+pub enum Event {
+    Connected(Descriptor),
+    Disconnected(u16),
+}
+
+/// Blocking iterator over connect and disconnect events, see [`events`]
+pub struct Events {
+    interval: time::Duration,
+    // PIDs present at the last scan, empty at first so present devices are reported
+    known: BTreeSet<u16>,
+    pending: VecDeque<Event>,
+    scanned: bool,
+}
+
+impl Events {
+    fn scan(&mut self) -> Result<()> {
+        let present: BTreeSet<u16> = Device::rescan_pids()?
+            .into_iter()
+            .filter(|&pid| descriptor::for_pid(pid).is_some())
+            .collect();
+        self.pending.extend(
+            self.known
+                .difference(&present)
+                .map(|&pid| Event::Disconnected(pid)),
+        );
+        self.pending.extend(
+            present
+                .difference(&self.known)
+                .filter_map(|&pid| descriptor::for_pid(pid))
+                .map(|d| Event::Connected(d.clone())),
+        );
+        self.known = present;
+        Ok(())
+    }
+}
+
+impl Iterator for Events {
+    type Item = Result<Event>;
+
+    // Never ends, a failed scan is yielded and the next call scans again
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() {
+            if self.scanned {
+                thread::sleep(self.interval);
+            }
+            self.scanned = true;
+            if let Err(e) = self.scan() {
+                return Some(Err(e));
+            }
+        }
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+/// Reports supported devices as they are connected and disconnected, starting with those
+/// already present. hidapi has no hotplug notifications, so the device list is rescanned
+/// every `interval`.
+pub fn events(interval: time::Duration) -> Events {
+    Events {
+        interval,
+        known: BTreeSet::new(),
+        pending: VecDeque::new(),
+        scanned: false,
+    }
+}
+
+/// Runs [`events`] on a background thread, for UI loops that poll a channel instead of blocking.
+/// The thread stops once the receiver is dropped and the next event is sent.
+pub fn subscribe(interval: time::Duration) -> mpsc::Receiver<Result<Event>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for event in events(interval) {
+            if tx.send(event).is_err() {
+                return;
+            }
+        }
+    });
+    rx
+}
@@ -1,4 +1,14 @@
+use crate::command;
+use crate::descriptor::{self, Descriptor};
+use crate::device::Device;
+use crate::types::{self, CpuBoost, FanMode, FanZone, GpuBoost, LogoMode, PerfMode};
+use crate::Error;
+use anyhow::{bail, ensure, Context, Result};
 use const_format::{map_ascii_case, Case};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::{PoisonError, RwLock};
+use strum::VariantNames;
 
 pub trait Feature {
     fn name(&self) -> &'static str;
@@ -68,6 +78,367 @@ feature_list![
     LidLogo,
     LightsAlwaysOn,
     KbdBacklight,
+    KbdRgb,
     Fan,
     Perf,
 ];
+
+/// Shape of a feature value as exchanged by [`get`] and [`set`]
+#[derive(Debug, Clone, Copy, Serialize)]
+pub enum ValueType {
+    Integer { min: i64, max: i64 },
+    // fan RPM, the range is the model's `Descriptor::fan_rpm_limits`
+    FanRpm,
+    Enum(&'static [&'static str]),
+    // "#rrggbb"
+    Color,
+    Object(&'static [(&'static str, ValueType)]),
+}
+
+impl ValueType {
+    /// JSON Schema accepting the values [`set`] takes for this type on any supported model
+    pub fn json_schema(&self) -> Value {
+        self.schema(descriptor::widest_fan_rpm_limits())
+    }
+
+    /// Like [`ValueType::json_schema`], with the RPM range of `descriptor`
+    pub fn json_schema_for(&self, descriptor: &Descriptor) -> Value {
+        self.schema(descriptor.fan_rpm_limits)
+    }
+
+    fn schema(&self, (rpm_min, rpm_max): (u16, u16)) -> Value {
+        match self {
+            ValueType::Integer { min, max } => {
+                serde_json::json!({ "type": "integer", "minimum": min, "maximum": max })
+            }
+            ValueType::FanRpm => {
+                serde_json::json!({ "type": "integer", "minimum": rpm_min, "maximum": rpm_max })
+            }
+            ValueType::Enum(variants) => serde_json::json!({ "enum": variants }),
+            ValueType::Color => {
+                serde_json::json!({ "type": "string", "pattern": "^#?[0-9a-fA-F]{6}$" })
+            }
+            ValueType::Object(fields) => serde_json::json!({
+                "type": "object",
+                "properties": fields
+                    .iter()
+                    .map(|(name, value_type)| {
+                        (name.to_string(), value_type.schema((rpm_min, rpm_max)))
+                    })
+                    .collect::<serde_json::Map<_, _>>(),
+                "additionalProperties": false,
+            }),
+        }
+    }
+}
+
+/// JSON Schema of an [`apply`] profile, generated from the feature metadata so it follows the enums
+pub fn profile_schema() -> Value {
+    schema_with_rpm(descriptor::widest_fan_rpm_limits())
+}
+
+/// Like [`profile_schema`], with the RPM range of `descriptor`
+pub fn profile_schema_for(descriptor: &Descriptor) -> Value {
+    schema_with_rpm(descriptor.fan_rpm_limits)
+}
+
+fn schema_with_rpm(rpm_limits: (u16, u16)) -> Value {
+    let properties: serde_json::Map<_, _> = all()
+        .map(|f| {
+            let mut schema = f.value_type.schema(rpm_limits);
+            schema["description"] = f.description.into();
+            (f.name.to_string(), schema)
+        })
+        .collect();
+    serde_json::json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "razer-cli profile",
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": false,
+    })
+}
+
+/// Feature metadata with generic accessors, so front ends can render controls without knowing each feature's types
+pub struct FeatureInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub value_type: ValueType,
+    get: fn(&Device) -> Result<Value>,
+    set: fn(&Device, Value) -> Result<()>,
+}
+
+impl FeatureInfo {
+    /// For features defined outside this crate, see [`register`]
+    pub const fn new(
+        name: &'static str,
+        description: &'static str,
+        value_type: ValueType,
+        get: fn(&Device) -> Result<Value>,
+        set: fn(&Device, Value) -> Result<()>,
+    ) -> Self {
+        Self {
+            name,
+            description,
+            value_type,
+            get,
+            set,
+        }
+    }
+
+    pub fn get(&self, device: &Device) -> Result<Value> {
+        (self.get)(device)
+    }
+
+    pub fn set(&self, device: &Device, value: Value) -> Result<()> {
+        (self.set)(device, value)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct FanValue {
+    mode: FanMode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rpm: Option<u16>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PerfValue {
+    mode: PerfMode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cpu: Option<CpuBoost>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gpu: Option<GpuBoost>,
+}
+
+macro_rules! unary_feature {
+    ($name:literal, $desc:literal, $value_type:expr, $getter:path, $setter:path) => {
+        FeatureInfo {
+            name: $name,
+            description: $desc,
+            value_type: $value_type,
+            get: |device| Ok(serde_json::to_value($getter(device)?)?),
+            set: |device, value| $setter(device, serde_json::from_value(value)?),
+        }
+    };
+}
+
+const FEATURES: &[FeatureInfo] = &[
+    unary_feature!(
+        "battery-care",
+        "Battery health optimizer",
+        ValueType::Enum(types::BatteryCare::VARIANTS),
+        command::get_battery_care,
+        command::set_battery_care
+    ),
+    unary_feature!(
+        "lid-logo",
+        "Lid logo mode",
+        ValueType::Enum(LogoMode::VARIANTS),
+        command::get_logo_mode,
+        command::set_logo_mode
+    ),
+    unary_feature!(
+        "lights-always-on",
+        "Keep lights on while the laptop is idle",
+        ValueType::Enum(types::LightsAlwaysOn::VARIANTS),
+        command::get_lights_always_on,
+        command::set_lights_always_on
+    ),
+    unary_feature!(
+        "kbd-backlight",
+        "Keyboard backlight brightness",
+        ValueType::Integer { min: 0, max: 255 },
+        command::get_keyboard_brightness,
+        command::set_keyboard_brightness
+    ),
+    // colors can't be read back, only the brightness
+    FeatureInfo {
+        name: "kbd-rgb",
+        description: "Keyboard brightness and static color, set without flicker in between",
+        value_type: ValueType::Object(&[
+            ("brightness", ValueType::Integer { min: 0, max: 255 }),
+            ("color", ValueType::Color),
+        ]),
+        get: |device| {
+            Ok(serde_json::to_value(types::LightingProfile {
+                brightness: Some(command::get_keyboard_brightness(device)?),
+                color: None,
+            })?)
+        },
+        set: |device, value| command::set_lighting_profile(device, &serde_json::from_value(value)?),
+    },
+    // before fan, whose settings depend on the performance mode, so `apply` sets it first
+    FeatureInfo {
+        name: "perf",
+        description: "Performance mode, cpu and gpu boost apply to custom mode only",
+        value_type: ValueType::Object(&[
+            ("mode", ValueType::Enum(PerfMode::VARIANTS)),
+            ("cpu", ValueType::Enum(CpuBoost::VARIANTS)),
+            ("gpu", ValueType::Enum(GpuBoost::VARIANTS)),
+        ]),
+        get: |device| {
+            let mode = command::get_perf_state(device)?.perf_mode;
+            let (cpu, gpu) = match mode {
+                PerfMode::Custom => (
+                    Some(command::get_cpu_boost(device)?),
+                    Some(command::get_gpu_boost(device)?),
+                ),
+                _ => (None, None),
+            };
+            Ok(serde_json::to_value(PerfValue { mode, cpu, gpu })?)
+        },
+        set: |device, value| {
+            let value: PerfValue = serde_json::from_value(value)?;
+            command::set_perf_mode(device, value.mode)?;
+            if let Some(cpu) = value.cpu {
+                command::set_cpu_boost(device, cpu)?;
+            }
+            if let Some(gpu) = value.gpu {
+                command::set_gpu_boost(device, gpu)?;
+            }
+            Ok(())
+        },
+    },
+    FeatureInfo {
+        name: "fan",
+        description: "Fan mode, rpm applies to manual mode only",
+        value_type: ValueType::Object(&[
+            ("mode", ValueType::Enum(FanMode::VARIANTS)),
+            ("rpm", ValueType::FanRpm),
+        ]),
+        get: |device| {
+            let mode = command::get_perf_state(device)?.fan_mode;
+            let rpm = match mode {
+                FanMode::Manual => Some(command::get_fan_rpm(device, FanZone::Zone1)?[0]),
+                FanMode::Auto => None,
+            };
+            Ok(serde_json::to_value(FanValue { mode, rpm })?)
+        },
+        set: |device, value| {
+            let value: FanValue = serde_json::from_value(value)?;
+            command::set_fan_mode(device, value.mode)?;
+            if let Some(rpm) = value.rpm {
+                command::set_fan_rpm(device, FanZone::All, rpm)?;
+            }
+            Ok(())
+        },
+    },
+];
+
+const _VALIDATE_FEATURE_INFO: () = {
+    const_for! { f in FEATURES => {
+        assert!(contains(ALL_FEATURES, f.name), "Feature is not in supported list");
+    }}
+};
+
+static REGISTERED: RwLock<Vec<&'static FeatureInfo>> = RwLock::new(Vec::new());
+
+/// All built-in features that can be read back, with their metadata, regardless of what a particular device supports
+pub fn features() -> &'static [FeatureInfo] {
+    FEATURES
+}
+
+/// Adds a feature defined outside this crate, e.g. by a plugin or a downstream binary.
+/// Registered features are not in any descriptor, so they are offered for every device
+/// and their accessors are expected to fail with `Error::Unsupported` where they don't apply.
+pub fn register(info: FeatureInfo) -> Result<()> {
+    let mut registered = REGISTERED.write().unwrap_or_else(PoisonError::into_inner);
+    ensure!(
+        FEATURES
+            .iter()
+            .chain(registered.iter().copied())
+            .all(|f| f.name != info.name),
+        "Feature {} is already registered",
+        info.name
+    );
+    registered.push(Box::leak(Box::new(info)));
+    Ok(())
+}
+
+/// Features added with [`register`], in registration order
+pub fn registered() -> Vec<&'static FeatureInfo> {
+    REGISTERED
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+// built-ins first, so `apply` keeps their order
+fn all() -> impl Iterator<Item = &'static FeatureInfo> {
+    FEATURES.iter().chain(registered())
+}
+
+pub fn find(name: &str) -> Result<&'static FeatureInfo> {
+    all()
+        .find(|f| f.name == name)
+        .ok_or_else(|| Error::Unsupported(format!("Unknown feature {}", name)).into())
+}
+
+// Fails on names that are unknown or that the device doesn't support, before anything is sent
+fn check_profile(device: &Device, profile: &serde_json::Map<String, Value>) -> Result<()> {
+    for name in profile.keys() {
+        find(name)?;
+        let built_in = FEATURES.iter().any(|f| f.name == name);
+        if built_in && !device.info.features.contains(&name.as_str()) {
+            bail!(Error::Unsupported(format!(
+                "{} is not supported by {}",
+                name, device.info.name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Sets every feature of `profile`, a map of feature names to values as returned by `get`.
+/// Features are set in `features()` order, then registration order, regardless of the map
+/// order, and all names are checked against the device before anything is sent.
+pub fn apply(device: &Device, profile: &serde_json::Map<String, Value>) -> Result<()> {
+    check_profile(device, profile)?;
+    all()
+        .filter_map(|f| Some((f, profile.get(f.name)?)))
+        .try_for_each(|(f, value)| {
+            f.set(device, value.clone())
+                .with_context(|| format!("Failed to set {}", f.name))
+        })
+}
+
+/// Features of a profile split by whether [`apply_if_changed`] sent them
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Applied {
+    pub changed: Vec<&'static str>,
+    pub unchanged: Vec<&'static str>,
+}
+
+/// Like [`apply`], but reads each feature first and only sets those whose value differs.
+/// Values are compared as JSON, so one written differently from what `get` returns, e.g.
+/// in another case, counts as changed and is sent.
+pub fn apply_if_changed(
+    device: &Device,
+    profile: &serde_json::Map<String, Value>,
+) -> Result<Applied> {
+    check_profile(device, profile)?;
+    let mut applied = Applied::default();
+    for (f, value) in all().filter_map(|f| Some((f, profile.get(f.name)?))) {
+        let sent = command::set_if_changed(
+            device,
+            value.clone(),
+            |device| f.get(device),
+            |device, value| f.set(device, value),
+        )
+        .with_context(|| format!("Failed to set {}", f.name))?;
+        match sent {
+            true => applied.changed.push(f.name),
+            false => applied.unchanged.push(f.name),
+        }
+    }
+    Ok(applied)
+}
+
+pub fn get(device: &Device, name: &str) -> Result<Value> {
+    find(name)?.get(device)
+}
+
+pub fn set(device: &Device, name: &str, value: Value) -> Result<()> {
+    find(name)?.set(device, value)
+}
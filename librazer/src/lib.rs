@@ -4,4 +4,13 @@ pub mod feature;
 pub mod types;
 
 pub mod descriptor;
-mod packet;
+mod error;
+pub mod event;
+pub mod packet;
+mod transport;
+
+pub use error::Error;
+pub use event::events;
+pub use feature::features;
+#[cfg(feature = "mock")]
+pub use transport::mock;
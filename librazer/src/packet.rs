@@ -1,4 +1,6 @@
+use crate::Error;
 use anyhow::{ensure, Result};
+use log::debug;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use serde_big_array::BigArray;
@@ -6,7 +8,7 @@ use serde_big_array::BigArray;
 /// Packet is the structure of the packet that is sent to the Razer HID device and received back.
 /// Source https://github.com/Razer-Linux/razer-laptop-control-no-dkms/blob/main/razer_control_gui/src/device.rs.
 #[repr(C)]
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Packet {
     status: u8,
     id: u8,
@@ -21,9 +23,26 @@ pub struct Packet {
     reserved: u8,
 }
 
+/// Checksum the firmware expects in the crc byte: XOR of bytes 2..88 of a serialized
+/// packet, i.e. everything from `remaining_packets` through the arguments. `bytes` starts
+/// at the status byte, without the HID report id; shorter input is covered up to its end.
+pub const fn compute_crc(bytes: &[u8]) -> u8 {
+    let end = if bytes.len() < 88 { bytes.len() } else { 88 };
+    let mut crc = 0;
+    let mut i = 2;
+    while i < end {
+        crc ^= bytes[i];
+        i += 1;
+    }
+    crc
+}
+
 enum CommandStatus {
     New = 0x00,
+    Busy = 0x01,
     Successful = 0x02,
+    Failure = 0x03,
+    Timeout = 0x04,
     NotSupported = 0x05,
 }
 
@@ -46,6 +65,15 @@ impl Packet {
         }
     }
 
+    pub fn command(&self) -> u16 {
+        (self.command_class as u16) << 8 | self.command_id as u16
+    }
+
+    /// Getters have the top bit of the command id set, e.g. 0x0d82 reads what 0x0d02 writes
+    pub fn is_read(&self) -> bool {
+        self.command_id & 0x80 != 0
+    }
+
     pub fn set_args(&mut self, args: &[u8]) {
         self.args[..args.len()].copy_from_slice(args)
     }
@@ -54,29 +82,61 @@ impl Packet {
         &self.args
     }
 
+    /// True while the device has not answered `report` yet: the buffer still holds
+    /// a previous transaction, or the command is not processed yet.
+    pub fn is_pending(&self, report: &Packet) -> bool {
+        self.id != report.id
+            || self.status == CommandStatus::New as u8
+            || self.status == CommandStatus::Busy as u8
+    }
+
+    /// The device is still working on a previous or the current command, worth waiting for
+    pub fn is_busy(&self) -> bool {
+        self.status == CommandStatus::Busy as u8
+    }
+
     pub fn ensure_matches_report(self, report: &Packet) -> Result<Self> {
         ensure!(
             (report.command_class, report.command_id, report.id)
                 == (self.command_class, self.command_id, self.id),
-            "Response does not match the report"
+            Error::Protocol("Response does not match the report".into())
         );
 
         ensure!(
             self.remaining_packets == report.remaining_packets
             || (self.command_class, self.command_id) == (0x07, 0x92) /* 0x0792 (bho) has special handling */
             || (self.command_class, self.command_id) == (0x07, 0x8f), /* 0x078f max fan speed mode has special handling */
-            "Response command does not match the report"
+            Error::Protocol("Response command does not match the report".into())
         );
 
         ensure!(
             self.status != CommandStatus::NotSupported as u8,
-            "Command not supported"
+            Error::Unsupported("Command not supported".into())
+        );
+
+        ensure!(
+            self.status != CommandStatus::Busy as u8,
+            Error::Protocol(
+                "Device stayed busy, another program such as Synapse may be using it".into()
+            )
+        );
+
+        ensure!(
+            self.status != CommandStatus::Failure as u8,
+            Error::Protocol("Command failed".into())
+        );
+
+        ensure!(
+            self.status != CommandStatus::Timeout as u8,
+            Error::Protocol("Command timed out in the firmware".into())
         );
 
         ensure!(
             self.status == CommandStatus::Successful as u8,
-            "Command failed with unknown status: {:02X?}",
-            self.status
+            Error::Protocol(format!(
+                "Command failed with unknown status: {:02X?}",
+                self.status
+            ))
         );
 
         Ok(self)
@@ -93,11 +153,67 @@ impl TryFrom<&[u8]> for Packet {
     type Error = anyhow::Error;
 
     fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
-        ensure!(
-            data.len() == std::mem::size_of::<Packet>(),
-            "Invalid raw data size"
-        );
+        let parsed = (|| {
+            let expected = std::mem::size_of::<Packet>();
+            ensure!(
+                data.len() == expected,
+                Error::Protocol(format!(
+                    "Malformed packet: expected {} bytes, got {}",
+                    expected,
+                    data.len()
+                ))
+            );
+            // byte 5, the firmware never sends more than the 80 argument bytes
+            ensure!(
+                data[5] as usize <= 80,
+                Error::Protocol(format!(
+                    "Malformed packet: data size {} at offset 5 exceeds 80 argument bytes",
+                    data[5]
+                ))
+            );
+            Ok(bincode::deserialize::<Packet>(data)?)
+        })();
+        if parsed.is_err() {
+            debug!("Malformed packet bytes: {:02x?}", data);
+        }
+        parsed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Wireshark export of the USB capture the protocol notes in data/ were taken from,
+    // one tab separated row per SET_REPORT with the 90 report bytes in hex in column 7
+    const CAPTURE: &str = include_str!("../../data/wireshark_dump_raw.csv");
+
+    fn captured_reports() -> Vec<Vec<u8>> {
+        CAPTURE
+            .lines()
+            .map(|line| {
+                let hex = line.split('\t').nth(6).unwrap();
+                (0..hex.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn crc_matches_every_captured_report() {
+        let reports = captured_reports();
+        assert!(!reports.is_empty());
+        for (row, report) in reports.iter().enumerate() {
+            assert_eq!(report.len(), 90, "row {}", row + 1);
+            assert_eq!(compute_crc(report), report[88], "row {}", row + 1);
+        }
+    }
 
-        Ok(bincode::deserialize::<Packet>(data)?)
+    #[test]
+    fn crc_covers_only_what_is_there() {
+        assert_eq!(compute_crc(&[]), 0);
+        assert_eq!(compute_crc(&[0xff, 0xff, 0x01, 0x02]), 0x03);
     }
 }
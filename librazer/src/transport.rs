@@ -0,0 +1,218 @@
+use anyhow::Result;
+use std::ffi::{CStr, CString};
+
+/// One open HID interface as `Device` talks to it: hidapi's handle, or a scripted double
+/// in tests
+pub(crate) trait Transport: Send {
+    fn send_feature_report(&self, data: &[u8]) -> hidapi::HidResult<()>;
+    fn get_feature_report(&self, buf: &mut [u8]) -> hidapi::HidResult<usize>;
+}
+
+impl Transport for hidapi::HidDevice {
+    fn send_feature_report(&self, data: &[u8]) -> hidapi::HidResult<()> {
+        hidapi::HidDevice::send_feature_report(self, data)
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> hidapi::HidResult<usize> {
+        hidapi::HidDevice::get_feature_report(self, buf)
+    }
+}
+
+/// Where `Device` finds its interfaces again after the device went away
+pub(crate) trait Bus: Send + Sync {
+    // scans the devices again, paths may have changed since the last scan
+    fn rescan(&self) -> Result<()>;
+    // interfaces of the Razer `pid` as of the last scan
+    fn interface_paths(&self, pid: u16) -> Result<Vec<CString>>;
+    fn open(&self, path: &CStr) -> Result<Box<dyn Transport>>;
+}
+
+#[cfg(any(test, feature = "mock"))]
+pub mod mock {
+    use super::{Bus, Transport};
+    use crate::device::{Device, DeviceConfig};
+    use crate::packet::Packet;
+    use anyhow::Result;
+    use std::collections::VecDeque;
+    use std::ffi::{CStr, CString};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    pub const BUSY: u8 = 0x01;
+    pub const SUCCESSFUL: u8 = 0x02;
+    pub const FAILURE: u8 = 0x03;
+    pub const NOT_SUPPORTED: u8 = 0x05;
+
+    // argument bytes start after the report id and 8 header bytes
+    const ARGS: usize = 9;
+
+    /// How the next response read is answered. Every reply echoes the last report sent,
+    /// as the firmware does, before changing it.
+    pub enum Reply {
+        Status(u8),
+        // successful, with these leading argument bytes
+        Args(Vec<u8>),
+        // successful, but claiming this many bytes were read
+        Size(usize),
+    }
+
+    #[derive(Default)]
+    struct Script {
+        replies: VecDeque<Reply>,
+        // once the replies ran out, echo with this status, successful when unset
+        default_status: Option<u8>,
+        gone: bool,
+        sent: Vec<Vec<u8>>,
+    }
+
+    fn gone() -> hidapi::HidError {
+        hidapi::HidError::HidApiError {
+            message: "No such device".into(),
+        }
+    }
+
+    /// Scripted HID interface, clones share the script
+    #[derive(Clone, Default)]
+    pub struct MockTransport(Arc<Mutex<Script>>);
+
+    impl MockTransport {
+        pub fn reply(&self, reply: Reply) -> &Self {
+            self.0.lock().unwrap().replies.push_back(reply);
+            self
+        }
+
+        pub fn answer_with(&self, status: u8) {
+            self.0.lock().unwrap().default_status = Some(status);
+        }
+
+        // every call fails from now on
+        pub fn go_away(&self) {
+            self.0.lock().unwrap().gone = true;
+        }
+
+        /// Full reports sent so far, leaving out probes
+        pub fn sent(&self) -> Vec<Packet> {
+            self.0
+                .lock()
+                .unwrap()
+                .sent
+                .iter()
+                .filter(|data| data.len() == 1 + std::mem::size_of::<Packet>())
+                .map(|data| Packet::try_from(&data[1..]).unwrap())
+                .collect()
+        }
+
+        /// (command, leading argument bytes) of the reports sent so far
+        pub fn commands(&self, args: usize) -> Vec<(u16, Vec<u8>)> {
+            self.sent()
+                .iter()
+                .map(|report| (report.command(), report.get_args()[..args].to_vec()))
+                .collect()
+        }
+
+        /// Device with this transport and no delays, on a bus that only knows it
+        pub fn device(&self) -> Device {
+            let bus = MockBus::new(vec![("mock", self.clone())], vec![("mock", self.clone())]);
+            self.device_on(bus, "mock")
+        }
+
+        pub fn device_on(&self, bus: MockBus, path: &str) -> Device {
+            let mut device = Device::from_transport(
+                Box::new(self.clone()),
+                Arc::new(bus),
+                &CString::new(path).unwrap(),
+                crate::descriptor::SUPPORTED[0].clone(),
+            );
+            device.set_config(DeviceConfig {
+                no_delays: true,
+                ..DeviceConfig::default()
+            });
+            device
+        }
+    }
+
+    impl Transport for MockTransport {
+        fn send_feature_report(&self, data: &[u8]) -> hidapi::HidResult<()> {
+            let mut script = self.0.lock().unwrap();
+            if script.gone {
+                return Err(gone());
+            }
+            script.sent.push(data.to_vec());
+            Ok(())
+        }
+
+        fn get_feature_report(&self, buf: &mut [u8]) -> hidapi::HidResult<usize> {
+            let mut script = self.0.lock().unwrap();
+            if script.gone {
+                return Err(gone());
+            }
+            if let Some(last) = script.sent.last() {
+                let len = last.len().min(buf.len());
+                buf[..len].copy_from_slice(&last[..len]);
+            }
+            buf[1] = script.default_status.unwrap_or(SUCCESSFUL);
+            match script.replies.pop_front() {
+                None => (),
+                Some(Reply::Status(status)) => buf[1] = status,
+                Some(Reply::Args(args)) => {
+                    buf[1] = SUCCESSFUL;
+                    buf[ARGS..ARGS + args.len()].copy_from_slice(&args);
+                }
+                Some(Reply::Size(size)) => return Ok(size),
+            }
+            Ok(buf.len())
+        }
+    }
+
+    /// Lists `before` until the first rescan and `after` from then on, e.g. a path that
+    /// lingers in the cached list after the device moved to another one
+    pub struct MockBus {
+        before: Vec<(CString, MockTransport)>,
+        after: Vec<(CString, MockTransport)>,
+        pub rescans: AtomicUsize,
+    }
+
+    impl MockBus {
+        pub fn new(before: Vec<(&str, MockTransport)>, after: Vec<(&str, MockTransport)>) -> Self {
+            let paths = |list: Vec<(&str, MockTransport)>| {
+                list.into_iter()
+                    .map(|(path, transport)| (CString::new(path).unwrap(), transport))
+                    .collect()
+            };
+            Self {
+                before: paths(before),
+                after: paths(after),
+                rescans: AtomicUsize::new(0),
+            }
+        }
+
+        fn listed(&self) -> &[(CString, MockTransport)] {
+            match self.rescans.load(Ordering::Relaxed) {
+                0 => &self.before,
+                _ => &self.after,
+            }
+        }
+    }
+
+    impl Bus for MockBus {
+        fn rescan(&self) -> Result<()> {
+            self.rescans.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn interface_paths(&self, _pid: u16) -> Result<Vec<CString>> {
+            Ok(self.listed().iter().map(|(path, _)| path.clone()).collect())
+        }
+
+        fn open(&self, path: &CStr) -> Result<Box<dyn Transport>> {
+            match self
+                .listed()
+                .iter()
+                .find(|(listed, _)| listed.as_c_str() == path)
+            {
+                Some((_, transport)) => Ok(Box::new(transport.clone())),
+                None => anyhow::bail!("Failed to open {:?}: No such device", path),
+            }
+        }
+    }
+}
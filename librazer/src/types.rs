@@ -1,41 +1,108 @@
-use anyhow::{bail, Result};
+use anyhow::{bail, ensure, Result};
 use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
-use strum_macros::{EnumIter, EnumString};
+use strum_macros::{EnumIter, EnumString, VariantNames};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub enum Cluster {
     Cpu = 0x01,
     Gpu = 0x02,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum FanZone {
     Zone1 = 0x01,
     Zone2 = 0x02,
+    // both zones, not a wire value
+    All,
+}
+
+impl FanZone {
+    pub fn zones(self) -> &'static [FanZone] {
+        match self {
+            FanZone::Zone1 => &[FanZone::Zone1],
+            FanZone::Zone2 => &[FanZone::Zone2],
+            FanZone::All => &[FanZone::Zone1, FanZone::Zone2],
+        }
+    }
 }
 
-#[derive(EnumIter, Clone, Copy, Debug, PartialEq, ValueEnum)]
+#[derive(
+    EnumString,
+    EnumIter,
+    VariantNames,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    ValueEnum,
+    Serialize,
+    Deserialize,
+)]
+#[strum(ascii_case_insensitive)]
 pub enum PerfMode {
     Balanced = 0,
     Turbo = 1,
+    Creator = 2,
     Silent = 5,
     Custom = 4,
 }
 
-#[derive(EnumIter, Clone, Copy, Debug, ValueEnum, PartialEq, Serialize, Deserialize)]
+impl PerfMode {
+    /// Modes in which the firmware takes a manual fan mode and so an RPM target
+    pub fn takes_manual_fan(self) -> bool {
+        matches!(self, PerfMode::Balanced | PerfMode::Turbo)
+    }
+}
+
+#[derive(
+    EnumString,
+    EnumIter,
+    VariantNames,
+    Clone,
+    Copy,
+    Debug,
+    ValueEnum,
+    PartialEq,
+    Serialize,
+    Deserialize,
+)]
+#[strum(ascii_case_insensitive)]
 pub enum MaxFanSpeedMode {
     Enable = 2,
     Disable = 0,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(
+    EnumString, EnumIter, VariantNames, Clone, Copy, Debug, PartialEq, Serialize, Deserialize,
+)]
+#[strum(ascii_case_insensitive)]
 pub enum FanMode {
     Auto = 0,
     Manual = 1,
 }
 
-#[derive(EnumIter, Clone, Copy, Debug, ValueEnum, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PerfState {
+    pub perf_mode: PerfMode,
+    pub fan_mode: FanMode,
+}
+
+#[derive(
+    EnumString,
+    EnumIter,
+    VariantNames,
+    Clone,
+    Copy,
+    Debug,
+    ValueEnum,
+    PartialEq,
+    Serialize,
+    Deserialize,
+)]
+#[strum(ascii_case_insensitive)]
 pub enum CpuBoost {
     Low = 0,
     Medium = 1,
@@ -44,7 +111,19 @@ pub enum CpuBoost {
     Overclock = 4,
 }
 
-#[derive(EnumIter, Clone, Copy, Debug, ValueEnum, PartialEq, Serialize, Deserialize)]
+#[derive(
+    EnumString,
+    EnumIter,
+    VariantNames,
+    Clone,
+    Copy,
+    Debug,
+    ValueEnum,
+    PartialEq,
+    Serialize,
+    Deserialize,
+)]
+#[strum(ascii_case_insensitive)]
 pub enum GpuBoost {
     Low = 0,
     Medium = 1,
@@ -52,26 +131,292 @@ pub enum GpuBoost {
 }
 
 #[derive(
-    EnumString, EnumIter, Clone, Copy, Debug, ValueEnum, PartialEq, Serialize, Deserialize,
+    EnumString,
+    EnumIter,
+    VariantNames,
+    Clone,
+    Copy,
+    Debug,
+    ValueEnum,
+    PartialEq,
+    Serialize,
+    Deserialize,
 )]
+#[strum(ascii_case_insensitive)]
 pub enum LogoMode {
     Off,
     Breathing,
     Static,
 }
 
-#[derive(EnumString, ValueEnum, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[derive(
+    EnumString,
+    EnumIter,
+    VariantNames,
+    ValueEnum,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Serialize,
+    Deserialize,
+)]
+#[strum(ascii_case_insensitive)]
 pub enum LightsAlwaysOn {
     Enable = 0x03,
     Disable = 0x00,
 }
 
-#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[derive(
+    EnumString,
+    EnumIter,
+    VariantNames,
+    ValueEnum,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Serialize,
+    Deserialize,
+)]
+#[strum(ascii_case_insensitive)]
 pub enum BatteryCare {
     Disable = 0x50,
     Enable = 0xd0,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl std::str::FromStr for Rgb {
+    type Err = anyhow::Error;
+
+    // "#rrggbb" or "rrggbb"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        ensure!(
+            hex.len() == 6 && hex.is_ascii(),
+            "Invalid color {}, expected #rrggbb",
+            s
+        );
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+        Ok(Self {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        })
+    }
+}
+
+impl TryFrom<String> for Rgb {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Rgb> for String {
+    fn from(rgb: Rgb) -> String {
+        format!("#{:02x}{:02x}{:02x}", rgb.r, rgb.g, rgb.b)
+    }
+}
+
+/// Keyboard lighting for `command::set_lighting_profile`, fields left out are not changed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LightingProfile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<u8>,
+    // the static effect in this color, the only effect known to take one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<Rgb>,
+}
+
+/// Full charge capacity against the design capacity, from `command::get_battery_health`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BatteryHealth {
+    pub design_mwh: u32,
+    pub full_mwh: u32,
+    pub cycle_count: Option<u32>,
+}
+
+impl BatteryHealth {
+    /// Share of the design capacity that is lost, 0 for a new battery
+    pub fn wear_pct(&self) -> f32 {
+        match self.design_mwh {
+            0 => 0.0,
+            design => (100.0 - self.full_mwh as f32 * 100.0 / design as f32).max(0.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BatteryStatus {
+    pub level_pct: u8,
+    // on AC power
+    pub plugged_in: bool,
+}
+
+/// CPU thermal throttling as the kernel counts it
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ThrottleState {
+    // a core, or the package as a whole, throttled while sampled
+    pub core: bool,
+    pub package: bool,
+    // events since boot
+    pub core_events: u64,
+    pub package_events: u64,
+}
+
+// GPU driving the internal display right now, whatever the mux is set to for the next boot
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ActiveGpu {
+    Integrated,
+    Discrete,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FanPoint {
+    pub temperature: u8,
+    pub rpm: u16,
+}
+
+/// Fan curve as (temperature, RPM) points with strictly increasing temperatures.
+/// Parsed from "40:2000,60:3000,80:5000" or deserialized from a list of points,
+/// both going through the same validation. The RPM range depends on the model, see
+/// [`FanProfile::ensure_within`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<FanPoint>", into = "Vec<FanPoint>")]
+pub struct FanProfile {
+    points: Vec<FanPoint>,
+}
+
+impl FanProfile {
+    pub fn points(&self) -> &[FanPoint] {
+        &self.points
+    }
+
+    /// Fails on the first point outside `(min, max)`, e.g. the descriptor's
+    /// `fan_rpm_limits`
+    pub fn ensure_within(&self, (min, max): (u16, u16)) -> Result<()> {
+        for (i, point) in self.points.iter().enumerate() {
+            ensure!(
+                (min..=max).contains(&point.rpm),
+                "Fan curve point {} ({}:{}): RPM must be in range [{}, {}]",
+                i + 1,
+                point.temperature,
+                point.rpm,
+                min,
+                max
+            );
+        }
+        Ok(())
+    }
+
+    /// RPM for `temperature`, interpolated linearly between the neighbouring points and held
+    /// at the first and last point outside them. Rounded to hundreds, the fan's resolution.
+    pub fn rpm_at(&self, temperature: f32) -> u16 {
+        let (first, last) = (self.points[0], self.points[self.points.len() - 1]);
+        let rpm = match self
+            .points
+            .windows(2)
+            .find(|pair| temperature < pair[1].temperature as f32)
+        {
+            _ if temperature <= first.temperature as f32 => first.rpm as f32,
+            Some(&[low, high]) => {
+                let t = (temperature - low.temperature as f32)
+                    / (high.temperature - low.temperature) as f32;
+                low.rpm as f32 + t * (high.rpm as f32 - low.rpm as f32)
+            }
+            _ => last.rpm as f32,
+        };
+        ((rpm / 100.0).round() * 100.0) as u16
+    }
+}
+
+impl TryFrom<Vec<FanPoint>> for FanProfile {
+    type Error = anyhow::Error;
+
+    fn try_from(points: Vec<FanPoint>) -> Result<Self, Self::Error> {
+        ensure!(!points.is_empty(), "Fan curve has no points");
+        for (i, point) in points.iter().enumerate() {
+            if let Some(previous) = i.checked_sub(1).map(|p| points[p]) {
+                ensure!(
+                    point.temperature > previous.temperature,
+                    "Fan curve point {} ({}:{}): temperature must be above the previous point's {}",
+                    i + 1,
+                    point.temperature,
+                    point.rpm,
+                    previous.temperature
+                );
+            }
+        }
+        Ok(Self { points })
+    }
+}
+
+impl From<FanProfile> for Vec<FanPoint> {
+    fn from(profile: FanProfile) -> Self {
+        profile.points
+    }
+}
+
+impl std::str::FromStr for FanProfile {
+    type Err = anyhow::Error;
+
+    // comma separated "temperature:rpm" pairs
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .enumerate()
+            .map(|(i, pair)| {
+                let parsed = pair.split_once(':').and_then(|(temperature, rpm)| {
+                    Some(FanPoint {
+                        temperature: temperature.trim().parse().ok()?,
+                        rpm: rpm.trim().parse().ok()?,
+                    })
+                });
+                parsed.ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Fan curve point {} ({}): expected temperature:rpm, e.g. 60:3000",
+                        i + 1,
+                        pair
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>>>()?
+            .try_into()
+    }
+}
+
+// Byte the firmware takes for each variant, the inverse of the TryFrom<u8> impls below
+macro_rules! impl_into_u8 {
+    ($($type:ty),*) => {
+        $(
+            impl From<$type> for u8 {
+                fn from(value: $type) -> u8 {
+                    value as u8
+                }
+            }
+        )*
+    };
+}
+
+impl_into_u8!(
+    PerfMode,
+    FanMode,
+    CpuBoost,
+    GpuBoost,
+    LightsAlwaysOn,
+    BatteryCare,
+    MaxFanSpeedMode
+);
+
 impl TryFrom<u8> for GpuBoost {
     type Error = anyhow::Error;
 
@@ -92,6 +437,7 @@ impl TryFrom<u8> for PerfMode {
         match perf_mode {
             0 => Ok(Self::Balanced),
             1 => Ok(Self::Turbo),
+            2 => Ok(Self::Creator),
             5 => Ok(Self::Silent),
             4 => Ok(Self::Custom),
             _ => bail!("Failed to convert {} to PerformanceMode", perf_mode),
@@ -162,3 +508,57 @@ impl TryFrom<u8> for MaxFanSpeedMode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use strum::IntoEnumIterator;
+
+    fn assert_round_trip<T>()
+    where
+        T: IntoEnumIterator + Into<u8> + TryFrom<u8> + Copy + PartialEq + std::fmt::Debug,
+    {
+        for variant in T::iter() {
+            let byte: u8 = variant.into();
+            assert_eq!(T::try_from(byte).ok(), Some(variant), "{:#04x}", byte);
+        }
+    }
+
+    #[test]
+    fn wire_bytes_round_trip() {
+        assert_round_trip::<PerfMode>();
+        assert_round_trip::<FanMode>();
+        assert_round_trip::<CpuBoost>();
+        assert_round_trip::<GpuBoost>();
+        assert_round_trip::<LightsAlwaysOn>();
+        assert_round_trip::<BatteryCare>();
+        assert_round_trip::<MaxFanSpeedMode>();
+    }
+
+    #[test]
+    fn unknown_bytes_are_rejected() {
+        assert!(PerfMode::try_from(3).is_err());
+        assert!(FanMode::try_from(2).is_err());
+        assert!(CpuBoost::try_from(5).is_err());
+        assert!(GpuBoost::try_from(3).is_err());
+        assert!(LightsAlwaysOn::try_from(1).is_err());
+        assert!(BatteryCare::try_from(0).is_err());
+        assert!(MaxFanSpeedMode::try_from(1).is_err());
+    }
+
+    #[test]
+    fn battery_care_reads_the_alternate_enable_byte() {
+        assert_eq!(BatteryCare::try_from(0xb2).unwrap(), BatteryCare::Enable);
+    }
+
+    #[test]
+    fn fan_profile_rpm_limits_come_from_the_caller() {
+        let profile: FanProfile = "40:1500,80:5000".parse().unwrap();
+        assert!(profile.ensure_within((1000, 5000)).is_ok());
+        let error = profile.ensure_within((2000, 5000)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Fan curve point 1 (40:1500): RPM must be in range [2000, 5000]"
+        );
+    }
+}
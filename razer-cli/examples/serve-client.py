@@ -0,0 +1,19 @@
+#!/usr/bin/env python3
+# Talks to `razer-cli auto serve --socket /tmp/razer.sock`, one JSON request per line.
+#   ./serve-client.py '{"cmd": "get_perf_state"}' '{"cmd": "set_fan_rpm", "args": {"rpm": 3200}}'
+import json
+import socket
+import sys
+
+SOCKET = "/tmp/razer.sock"
+
+with socket.socket(socket.AF_UNIX, socket.SOCK_STREAM) as sock:
+    sock.connect(SOCKET)
+    lines = sock.makefile("rw")
+    for request in sys.argv[1:] or ['{"cmd": "info"}']:
+        lines.write(request + "\n")
+        lines.flush()
+        response = json.loads(lines.readline())
+        if not response["ok"]:
+            sys.exit(f"error: {response['error']}")
+        print(json.dumps(response.get("value"), indent=2))
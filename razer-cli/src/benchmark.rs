@@ -0,0 +1,116 @@
+use anyhow::Result;
+use librazer::command;
+use librazer::device::{Device, DeviceConfig};
+use log::{debug, info};
+use std::time::{Duration, Instant};
+
+// tried from the shortest, the first without failures is suggested
+const PRE_WRITE_DELAYS_US: [u64; 5] = [0, 250, 500, 750, 1000];
+// added to the delay adaptive timing settles on, which is just enough for an idle device
+const POST_WRITE_MARGIN: Duration = Duration::from_micros(100);
+
+struct Stats {
+    latencies: Vec<Duration>,
+    failures: usize,
+    retries: u64,
+}
+
+impl Stats {
+    fn calls(&self) -> usize {
+        self.latencies.len() + self.failures
+    }
+
+    fn percentile(&self, p: usize) -> Duration {
+        let mut sorted = self.latencies.clone();
+        sorted.sort();
+        sorted[(sorted.len() * p).div_ceil(100).saturating_sub(1)]
+    }
+
+    fn log(&self, label: &str) {
+        if self.latencies.is_empty() {
+            info!("{}: all {} calls failed", label, self.failures);
+            return;
+        }
+        let total: Duration = self.latencies.iter().sum();
+        info!(
+            "{}: min {:.1?}, avg {:.1?}, max {:.1?}, p99 {:.1?}, {:.1}% failed, {:.2} retries per call",
+            label,
+            self.latencies.iter().min().unwrap(),
+            total / self.latencies.len() as u32,
+            self.latencies.iter().max().unwrap(),
+            self.percentile(99),
+            100.0 * self.failures as f64 / self.calls() as f64,
+            self.retries as f64 / self.calls() as f64,
+        );
+    }
+}
+
+// Times `count` performance mode reads, a getter every supported model answers
+fn measure(device: &Device, count: usize) -> Stats {
+    let retries = device.retries();
+    let mut stats = Stats {
+        latencies: Vec::with_capacity(count),
+        failures: 0,
+        retries: 0,
+    };
+    for _ in 0..count {
+        let started = Instant::now();
+        match command::get_perf_state(device) {
+            Ok(_) => stats.latencies.push(started.elapsed()),
+            Err(e) => {
+                debug!("Benchmark call failed: {:#}", e);
+                stats.failures += 1;
+            }
+        }
+    }
+    stats.retries = device.retries() - retries;
+    stats
+}
+
+/// Measures round trips with the current settings, then lets adaptive timing find the
+/// post write delay and tries shorter pre write delays with it, suggesting the result.
+pub fn run(device: &Device, count: usize) -> Result<()> {
+    let config = device.config().clone();
+    // a handle of our own to change the timing on
+    let mut device = device.reopen()?;
+    measure(&device, count).log("Current settings");
+
+    device.set_config(DeviceConfig {
+        adaptive_timing: true,
+        no_delays: false,
+        ..config.clone()
+    });
+    let adaptive = measure(&device, count);
+    adaptive.log("Adaptive timing");
+    let post_write_delay = device.response_delay() + POST_WRITE_MARGIN;
+
+    let mut pre_write_delay = None;
+    for delay in PRE_WRITE_DELAYS_US.map(Duration::from_micros) {
+        device.set_config(DeviceConfig {
+            adaptive_timing: false,
+            no_delays: false,
+            pre_write_delay: delay,
+            post_write_delay,
+            ..config.clone()
+        });
+        let stats = measure(&device, count);
+        stats.log(&format!(
+            "Pre write {:?}, post write {:?}",
+            delay, post_write_delay
+        ));
+        if stats.failures == 0 {
+            pre_write_delay = Some(delay);
+            break;
+        }
+    }
+
+    match pre_write_delay {
+        Some(pre_write_delay) => info!(
+            "Suggested: --pre-write-delay {} --post-write-delay {}",
+            pre_write_delay.as_micros(),
+            post_write_delay.as_micros()
+        ),
+        None => info!("Every tried delay had failures, keep the defaults"),
+    }
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+const APP_NAME: &str = "razer-ctl";
+const CONFIG_NAME: &str = "config";
+const BOOT_PROFILE_NAME: &str = "boot-profile.json";
+const HISTORY_NAME: &str = "history.jsonl";
+// a packet holds at most this many argument bytes
+const MAX_COMMAND_ARGS: usize = 80;
+
+// Defaults read from ~/.config/razer-ctl/config.toml, overridden by command line flags
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    // PID opened by `auto` instead of detecting the model
+    pub pid: Option<u16>,
+    // error, warn, info, debug or trace
+    pub log_level: Option<String>,
+    // sends again of a command that timed out, like --retries
+    pub retries: Option<u32>,
+    // of fan curve points and logged temperatures, sensors are still read in Celsius
+    #[serde(default)]
+    pub temperature_unit: TemperatureUnit,
+    // refuse CPU overclock on battery, like --battery-guard
+    #[serde(default)]
+    pub battery_guard: bool,
+    // append every changed setting to history_path(), for `razer-cli history`
+    #[serde(default)]
+    pub history: bool,
+    // named custom commands for `cmd run`, e.g. [aliases.perf-zone1]
+    #[serde(default)]
+    pub aliases: BTreeMap<String, Alias>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+impl TemperatureUnit {
+    // a temperature read in °C, in this unit
+    pub fn convert(self, celsius: f32) -> f32 {
+        match self {
+            TemperatureUnit::Celsius => celsius,
+            TemperatureUnit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            TemperatureUnit::Celsius => "°C",
+            TemperatureUnit::Fahrenheit => "°F",
+        }
+    }
+}
+
+// A command with its arguments, documented once instead of retyped in hex
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Alias {
+    pub command: u16,
+    #[serde(default)]
+    pub args: Vec<u8>,
+    // shown by `cmd list`
+    pub description: Option<String>,
+}
+
+impl Config {
+    // A missing file gives the defaults, no file is created
+    pub fn load() -> Result<Self> {
+        let path = confy::get_configuration_file_path(APP_NAME, CONFIG_NAME)?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let config: Self = confy::load_path(&path)
+            .with_context(|| format!("Failed to load {}", path.display()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (name, alias) in &self.aliases {
+            ensure!(
+                alias.args.len() <= MAX_COMMAND_ARGS,
+                "Alias {} in config has {} args, a command takes at most {}",
+                name,
+                alias.args.len(),
+                MAX_COMMAND_ARGS
+            );
+        }
+        Ok(())
+    }
+
+    pub fn log_level(&self) -> Result<Option<log::LevelFilter>> {
+        self.log_level
+            .as_deref()
+            .map(|level| {
+                level
+                    .parse()
+                    .with_context(|| format!("Invalid log_level {:?} in config", level))
+            })
+            .transpose()
+    }
+}
+
+// Written by `save-boot-profile` and read by `apply-boot-profile`, next to the config file
+pub fn boot_profile_path() -> Result<PathBuf> {
+    let config_path = confy::get_configuration_file_path(APP_NAME, CONFIG_NAME)?;
+    Ok(config_path.with_file_name(BOOT_PROFILE_NAME))
+}
+
+// ~/.local/state/razer-ctl/history.jsonl on Linux, honoring XDG_STATE_HOME, next to the
+// config file elsewhere
+pub fn history_path() -> Result<PathBuf> {
+    #[cfg(target_os = "linux")]
+    {
+        let state_dir = std::env::var_os("XDG_STATE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")));
+        if let Some(dir) = state_dir {
+            return Ok(dir.join(APP_NAME).join(HISTORY_NAME));
+        }
+    }
+    let config_path = confy::get_configuration_file_path(APP_NAME, CONFIG_NAME)?;
+    Ok(config_path.with_file_name(HISTORY_NAME))
+}
+
+// Brightness `kbd-backlight toggle` turns back on to, one file per device model
+pub fn last_brightness_path(pid: u16) -> Result<PathBuf> {
+    let config_path = confy::get_configuration_file_path(APP_NAME, CONFIG_NAME)?;
+    Ok(config_path.with_file_name(format!("kbd-backlight-{:04x}", pid)))
+}
+
+// Writes a temporary file next to `path` and renames it over `path`, so readers
+// never see a half-written file even if we crash midway
+pub fn write_atomic(path: &Path, contents: &str) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    std::fs::write(&temp, contents)
+        .and_then(|_| std::fs::rename(&temp, path))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
@@ -0,0 +1,210 @@
+use anyhow::{Context, Result};
+use librazer::types::{FanProfile, PerfMode};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// Read by `fan curve`, e.g.
+//   temp_source = { hwmon = "/sys/class/hwmon/hwmon2/temp1_input" }
+//   curve = [{ temperature = 40, rpm = 2000 }, { temperature = 80, rpm = 5000 }]
+//   [curves]
+//   Turbo = [{ temperature = 40, rpm = 3000 }, { temperature = 70, rpm = 5000 }]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CurveConfig {
+    // for the modes without an entry in `curves`
+    #[serde(default)]
+    pub curve: Option<FanProfile>,
+    #[serde(default)]
+    pub curves: HashMap<PerfMode, FanProfile>,
+    pub temp_source: TempSource,
+    // seconds between temperature reads
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+fn default_interval() -> u64 {
+    2
+}
+
+// No HID command for the CPU temperature is known, so it comes from the OS
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TempSource {
+    // sysfs file in millidegrees Celsius
+    Hwmon(PathBuf),
+    // shell command printing degrees Celsius
+    Command(String),
+}
+
+impl CurveConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&contents).with_context(|| format!("Invalid fan curve {}", path.display()))
+    }
+
+    /// An inline curve such as "40:2000,60:3000,80:5000", used in every mode that takes
+    /// manual RPM
+    pub fn inline(spec: &str, temp_source: TempSource, interval: Option<u64>) -> Result<Self> {
+        Ok(Self {
+            curve: Some(spec.parse().context("Invalid inline fan curve")?),
+            curves: HashMap::new(),
+            temp_source,
+            interval: interval.unwrap_or_else(default_interval),
+        })
+    }
+
+    fn parse(contents: &str) -> Result<Self> {
+        let config: Self = toml::from_str(contents)?;
+        anyhow::ensure!(
+            config.curve.is_some() || !config.curves.is_empty(),
+            "Neither a curve nor [curves] is given"
+        );
+        if let Some(mode) = config.curves.keys().find(|mode| !mode.takes_manual_fan()) {
+            anyhow::bail!("{:?} mode takes no manual RPM, it can't have a curve", mode);
+        }
+        Ok(config)
+    }
+
+    /// Fails on the first curve with a point outside the device's `(min, max)` RPM
+    pub fn ensure_within(&self, limits: (u16, u16)) -> Result<()> {
+        if let Some(curve) = &self.curve {
+            curve.ensure_within(limits).context("Invalid fan curve")?;
+        }
+        for (mode, curve) in &self.curves {
+            curve
+                .ensure_within(limits)
+                .with_context(|| format!("Invalid {:?} fan curve", mode))?;
+        }
+        Ok(())
+    }
+
+    /// Curve to follow in `mode`, None where the firmware's own curve runs
+    pub fn curve_for(&self, mode: PerfMode) -> Option<&FanProfile> {
+        match mode.takes_manual_fan() {
+            true => self.curves.get(&mode).or(self.curve.as_ref()),
+            false => None,
+        }
+    }
+}
+
+impl TempSource {
+    // degrees Celsius
+    pub fn read(&self) -> Result<f32> {
+        match self {
+            TempSource::Hwmon(path) => {
+                let millidegrees: f32 = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("{} does not hold a number", path.display()))?;
+                Ok(millidegrees / 1000.0)
+            }
+            TempSource::Command(command) => {
+                #[cfg(target_os = "windows")]
+                let output = std::process::Command::new("cmd")
+                    .args(["/C", command])
+                    .output();
+                #[cfg(not(target_os = "windows"))]
+                let output = std::process::Command::new("sh")
+                    .args(["-c", command])
+                    .output();
+                let output = output.with_context(|| format!("Failed to run {:?}", command))?;
+                anyhow::ensure!(
+                    output.status.success(),
+                    "{:?} failed with {}",
+                    command,
+                    output.status
+                );
+                String::from_utf8_lossy(&output.stdout)
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("{:?} did not print a number", command))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMP_SOURCE: &str = "temp_source = { hwmon = \"/dev/null\" }\n";
+
+    #[test]
+    fn picks_the_curve_of_the_mode() {
+        let config = CurveConfig::parse(&format!(
+            "{}curve = [{{ temperature = 50, rpm = 2000 }}]\n[curves]\nTurbo = [{{ temperature = 50, rpm = 4000 }}]\n",
+            TEMP_SOURCE
+        ))
+        .unwrap();
+        let rpm = |mode| config.curve_for(mode).map(|curve| curve.rpm_at(50.0));
+        assert_eq!(rpm(PerfMode::Balanced), Some(2000));
+        assert_eq!(rpm(PerfMode::Turbo), Some(4000));
+        assert_eq!(rpm(PerfMode::Silent), None);
+        assert_eq!(rpm(PerfMode::Custom), None);
+    }
+
+    #[test]
+    fn curves_alone_leave_other_modes_to_the_firmware() {
+        let config = CurveConfig::parse(&format!(
+            "{}[curves]\nTurbo = [{{ temperature = 50, rpm = 4000 }}]\n",
+            TEMP_SOURCE
+        ))
+        .unwrap();
+        assert!(config.curve_for(PerfMode::Balanced).is_none());
+        assert!(config.curve_for(PerfMode::Turbo).is_some());
+    }
+
+    #[test]
+    fn rejects_missing_curves_and_modes_without_manual_rpm() {
+        assert!(CurveConfig::parse(TEMP_SOURCE).is_err());
+        assert!(CurveConfig::parse(&format!(
+            "{}[curves]\nSilent = [{{ temperature = 50, rpm = 2000 }}]\n",
+            TEMP_SOURCE
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn checks_every_curve_against_the_device_limits() {
+        let config = CurveConfig::parse(&format!(
+            "{}curve = [{{ temperature = 50, rpm = 2000 }}]\n[curves]\nTurbo = [{{ temperature = 50, rpm = 5500 }}]\n",
+            TEMP_SOURCE
+        ))
+        .unwrap();
+        assert!(config.ensure_within((2000, 6000)).is_ok());
+        let error = config.ensure_within((2000, 5000)).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid Turbo fan curve");
+        assert!(config.ensure_within((2500, 6000)).is_err());
+    }
+
+    fn inline_error(spec: &str) -> String {
+        let source = TempSource::Hwmon("/dev/null".into());
+        format!("{:#}", CurveConfig::inline(spec, source, None).unwrap_err())
+    }
+
+    #[test]
+    fn inline_curve_is_used_in_every_manual_mode() {
+        let source = TempSource::Command("echo 60".into());
+        let config = CurveConfig::inline("40:2000, 80:4000", source, Some(5)).unwrap();
+        assert_eq!(config.interval, 5);
+        let rpm = |mode| config.curve_for(mode).map(|curve| curve.rpm_at(60.0));
+        assert_eq!(rpm(PerfMode::Balanced), Some(3000));
+        assert_eq!(rpm(PerfMode::Turbo), Some(3000));
+        assert_eq!(rpm(PerfMode::Silent), None);
+    }
+
+    #[test]
+    fn inline_curve_errors_point_at_the_bad_pair() {
+        assert_eq!(
+            inline_error("40:2000,30:3000"),
+            "Invalid inline fan curve: Fan curve point 2 (30:3000): temperature must be above the previous point's 40"
+        );
+        assert_eq!(
+            inline_error("40:2000,60-3000"),
+            "Invalid inline fan curve: Fan curve point 2 (60-3000): expected temperature:rpm, e.g. 60:3000"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use crate::{config, raw_value};
+use anyhow::{Context, Result};
+use librazer::device::Device;
+use librazer::feature;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::Write;
+
+// One line of the history file
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    // RFC 3339, UTC
+    time: String,
+    user: String,
+    device: String,
+    feature: String,
+    old: Value,
+    new: Value,
+}
+
+fn user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// Current values of the `names` features, leaving out those that can't be read
+pub fn snapshot(device: &Device, names: &[&str]) -> Vec<(String, Value)> {
+    names
+        .iter()
+        .filter_map(|&name| match feature::get(device, name) {
+            Ok(value) => Some((name.to_string(), value)),
+            Err(e) => {
+                debug!("Not tracking {}: {:#}", name, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Appends a line per feature that differs between the snapshots
+pub fn record(
+    device: &Device,
+    before: &[(String, Value)],
+    after: &[(String, Value)],
+) -> Result<()> {
+    let time = humantime::format_rfc3339_seconds(std::time::SystemTime::now()).to_string();
+    let lines: String = before
+        .iter()
+        .filter_map(|(name, old)| {
+            let (_, new) = after.iter().find(|(n, _)| n == name)?;
+            (old != new).then(|| Entry {
+                time: time.clone(),
+                user: user(),
+                device: device.info.name.to_string(),
+                feature: name.clone(),
+                old: old.clone(),
+                new: new.clone(),
+            })
+        })
+        .map(|entry| serde_json::to_string(&entry).map(|line| line + "\n"))
+        .collect::<Result<_, _>>()?;
+    if lines.is_empty() {
+        return Ok(());
+    }
+    let path = config::history_path()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(lines.as_bytes()))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Logs the last `count` changes, oldest first
+pub fn print(count: usize) -> Result<()> {
+    let path = config::history_path()?;
+    let Ok(contents) = std::fs::read_to_string(&path) else {
+        info!("No history yet, enable it with `history = true` in config.toml");
+        return Ok(());
+    };
+    let lines: Vec<&str> = contents.lines().collect();
+    for line in &lines[lines.len().saturating_sub(count)..] {
+        let entry: Entry = serde_json::from_str(line)
+            .with_context(|| format!("Malformed line in {}: {}", path.display(), line))?;
+        info!(
+            "{} {} {}: {} {} -> {}",
+            entry.time,
+            entry.user,
+            entry.device,
+            entry.feature,
+            raw_value(&entry.old),
+            raw_value(&entry.new)
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+// Held by a daemon for as long as it controls the device. The OS drops the lock when the
+// process exits, so a lock file left behind by a crash doesn't block the next start.
+pub struct DaemonLock {
+    _file: File,
+}
+
+// In the user's runtime directory, or named after the user in /run/lock: a file another
+// user created in a shared sticky directory can't be opened, see fs.protected_regular
+fn lock_path(pid: u16) -> PathBuf {
+    if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        return PathBuf::from(dir).join(format!("razer-cli-{:04x}.lock", pid));
+    }
+    let dir = match std::path::Path::new("/run/lock") {
+        dir if cfg!(unix) && dir.is_dir() => dir.to_path_buf(),
+        _ => std::env::temp_dir(),
+    };
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_default();
+    dir.join(format!("razer-cli-{}-{:04x}.lock", user, pid))
+}
+
+pub fn acquire(pid: u16) -> Result<DaemonLock> {
+    let path = lock_path(pid);
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    match file.try_lock() {
+        Ok(()) => {
+            file.set_len(0)?;
+            write!(file, "{}", std::process::id())?;
+            Ok(DaemonLock { _file: file })
+        }
+        Err(std::fs::TryLockError::WouldBlock) => {
+            let holder = std::fs::read_to_string(&path).unwrap_or_default();
+            anyhow::bail!(
+                "Another razer-cli daemon (process {}) is already controlling device {:#06x}, stop it first",
+                holder.trim(),
+                pid
+            )
+        }
+        Err(std::fs::TryLockError::Error(e)) => {
+            Err(e).with_context(|| format!("Failed to lock {}", path.display()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_acquire_names_the_holder() {
+        // no device has this PID
+        let _held = acquire(0xfff1).unwrap();
+        let error = acquire(0xfff1).err().unwrap().to_string();
+        assert!(
+            error.contains(&format!("(process {})", std::process::id())),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use anyhow::Result;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const MAX_LOG_FILE_SIZE: u64 = 10 << 20;
+
+// Appends to `path`, moving it to `path.1` once it grows past MAX_LOG_FILE_SIZE
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            size: file.metadata()?.len(),
+            file,
+        })
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        let mut backup = self.path.clone().into_os_string();
+        backup.push(".1");
+        std::fs::rename(&self.path, backup)?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.size + buf.len() as u64 > MAX_LOG_FILE_SIZE {
+            self.rotate()?;
+        }
+        self.file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+// Forwards every record to each logger whose own filter accepts it
+struct TeeLogger {
+    loggers: Vec<env_logger::Logger>,
+}
+
+impl log::Log for TeeLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.loggers.iter().any(|l| l.enabled(metadata))
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.loggers.iter().for_each(|l| l.log(record));
+    }
+
+    fn flush(&self) {
+        self.loggers.iter().for_each(|l| l.flush());
+    }
+}
+
+pub fn init(
+    console_level: log::LevelFilter,
+    file_level: log::LevelFilter,
+    write_style: env_logger::WriteStyle,
+    log_file: Option<&Path>,
+) -> Result<()> {
+    // RUST_LOG still overrides the flags
+    let mut loggers = vec![env_logger::Builder::new()
+        .filter_level(console_level)
+        .parse_default_env()
+        .format_timestamp(None)
+        .write_style(write_style)
+        .build()];
+
+    if let Some(path) = log_file {
+        loggers.push(
+            env_logger::Builder::new()
+                .filter_level(file_level)
+                .parse_default_env()
+                .format_timestamp_secs()
+                .write_style(env_logger::WriteStyle::Never)
+                .target(env_logger::Target::Pipe(Box::new(RotatingFile::open(
+                    path,
+                )?)))
+                .build(),
+        );
+    }
+
+    let max_level = loggers.iter().map(|l| l.filter()).max().unwrap();
+    log::set_boxed_logger(Box::new(TeeLogger { loggers }))?;
+    log::set_max_level(max_level);
+    Ok(())
+}
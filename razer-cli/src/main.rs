@@ -1,27 +1,44 @@
 use librazer::command;
+use librazer::descriptor::{self, DeviceClass};
 use librazer::device;
 use librazer::feature;
-use librazer::descriptor::SUPPORTED;
 use librazer::types::{
     BatteryCare, CpuBoost, FanMode, FanZone, GpuBoost, LightsAlwaysOn, LogoMode, MaxFanSpeedMode,
-    PerfMode,
+    PerfMode, Rgb,
 };
 
 use librazer::feature::Feature;
 
-use anyhow::Result;
+mod benchmark;
+mod config;
+mod curve;
+mod history;
+mod lock;
+mod logging;
+mod notify;
+mod report;
+mod server;
+mod tui;
+
+use anyhow::{Context, Result};
 use clap::{arg, Command};
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 
 trait Cli: feature::Feature {
     fn cmd(&self) -> Option<Command> {
         None
     }
-    fn handle(&self, _device: &device::Device, _matches: &clap::ArgMatches) -> Result<()> {
+    fn handle(
+        &self,
+        _device: &device::Device,
+        _matches: &clap::ArgMatches,
+        _config: &config::Config,
+    ) -> Result<()> {
         Ok(())
     }
-    fn notify(&self, message: &str) {
-        info!("{}", message);
+    // prefixed with the device name so notifications from different devices can be told apart
+    fn notify(&self, device: &device::Device, message: &str) {
+        notify::send(&format!("{}: {}", device.info.name, message));
     }
 }
 
@@ -36,18 +53,97 @@ macro_rules! impl_unary_cmd_cli {
 }
 
 macro_rules! impl_unary_handle_cli {
-    (<$arg_type:ty>($matches:ident, $device:ident, $name:literal, $arg_name:literal, $setter:path)) => {
+    (<$arg_type:ty>($matches:ident, $device:ident, $name:literal, $arg_name:literal, $setter:path, $getter:path)) => {
         match $matches.subcommand() {
             Some(($name, matches)) => {
-                $setter($device, *matches.get_one::<$arg_type>($arg_name).unwrap())?
+                let arg = *matches.get_one::<$arg_type>($arg_name).unwrap();
+                write_setting(matches, $device, arg, $setter, $getter)?;
+                verify(matches, arg, || $getter($device))?
             }
             _ => (),
         }
     };
 }
 
+// With --skip-unchanged, reads the setting first and only sends it when it differs
+fn write_setting<T: PartialEq + std::fmt::Debug + Copy>(
+    matches: &clap::ArgMatches,
+    device: &device::Device,
+    value: T,
+    setter: impl FnOnce(&device::Device, T) -> Result<()>,
+    getter: impl FnOnce(&device::Device) -> Result<T>,
+) -> Result<()> {
+    if !matches.get_flag("skip-unchanged") {
+        return setter(device, value);
+    }
+    if !command::set_if_changed(device, value, getter, setter)? {
+        debug!("Already {:?}, not sent", value);
+    }
+    Ok(())
+}
+
+// With --verify, reads the setting back and fails if the device didn't apply it
+fn verify<T: PartialEq + std::fmt::Debug>(
+    matches: &clap::ArgMatches,
+    expected: T,
+    getter: impl FnOnce() -> Result<T>,
+) -> Result<()> {
+    if !matches.get_flag("verify") {
+        return Ok(());
+    }
+    let actual = getter()?;
+    if actual != expected {
+        return Err(librazer::Error::VerificationFailed {
+            expected: format!("{:?}", expected),
+            actual: format!("{:?}", actual),
+        }
+        .into());
+    }
+    debug!("Verified {:?}", actual);
+    Ok(())
+}
+
+// With --battery-guard or battery_guard in the config, refuses `setting` unless the charger
+// is plugged in. Where the power source can't be read it is refused too, the guard was asked for.
+fn battery_guard(
+    matches: &clap::ArgMatches,
+    device: &device::Device,
+    config: &config::Config,
+    setting: &str,
+) -> Result<()> {
+    if !matches.get_flag("battery-guard") && !config.battery_guard {
+        return Ok(());
+    }
+    match command::get_battery_status(device) {
+        Ok(status) => anyhow::ensure!(
+            status.plugged_in,
+            "Refusing {} on battery, it can brown out some models. Plug in the charger, or drop --battery-guard and battery_guard from config.toml",
+            setting
+        ),
+        Err(e) => anyhow::bail!(
+            "Refusing {}, the battery guard can't tell the power source: {:#}. Drop --battery-guard and battery_guard from config.toml to allow it",
+            setting,
+            e
+        ),
+    }
+    Ok(())
+}
+
+// Whether an `apply` profile sets CPU overclock, parsed like `feature::apply` does
+fn profile_overclocks(profile: &serde_json::Map<String, serde_json::Value>) -> bool {
+    profile
+        .get("perf")
+        .and_then(|perf| perf.get("cpu"))
+        .and_then(|cpu| serde_json::from_value::<CpuBoost>(cpu.clone()).ok())
+        == Some(CpuBoost::Overclock)
+}
+
 macro_rules! impl_unary_cli {
     (<$feature_type:ty><$arg_type:ty>($desc:literal,$arg_desc:literal,$setter:path,$getter:path)) => {
+        impl_unary_cli! {<$feature_type><$arg_type>($desc, $arg_desc, $setter, $getter, |_, value| format!("{:?}", value))}
+    };
+    // `$show` formats the getter's value for `info`, it may read more from the device
+    (<$feature_type:ty><$arg_type:ty>($desc:literal,$arg_desc:literal,$setter:path,$getter:path,$show:expr)) => {
         impl Cli for $feature_type {
             fn cmd(&self) -> Option<Command> {
                 Some(
@@ -57,12 +153,13 @@ macro_rules! impl_unary_cli {
                         .arg_required_else_help(true),
                 )
             }
-            fn handle(&self, device: &device::Device, matches: &clap::ArgMatches) -> Result<()> {
+            fn handle(&self, device: &device::Device, matches: &clap::ArgMatches, _config: &config::Config) -> Result<()> {
                 match matches.subcommand() {
                     Some((ident, matches)) if ident == self.name() => {
                         let arg = matches.get_one::<$arg_type>("ARG").unwrap();
-                        $setter(device, *arg)?;
-                        self.notify(&format!(
+                        write_setting(matches, device, *arg, $setter, $getter)?;
+                        verify(matches, *arg, || $getter(device))?;
+                        self.notify(device, &format!(
                             "{} set to {:?}",
                             self.name().replace('-', " "),
                             arg
@@ -70,7 +167,8 @@ macro_rules! impl_unary_cli {
                         Ok(())
                     }
                     Some(("info", _)) => {
-                        info!("{}: {:?}", self.name(), $getter(device)?);
+                        let show: fn(&device::Device, $arg_type) -> String = $show;
+                        info!("{}: {}", self.name(), show(device, $getter(device)?));
                         Ok(())
                     }
                     _ => Ok(()),
@@ -80,10 +178,260 @@ macro_rules! impl_unary_cli {
     }
 }
 
-impl_unary_cli! {<feature::KbdBacklight><u8>("Set keyboard backlight brightness", "Number in range [0, 255]", command::set_keyboard_brightness, command::get_keyboard_brightness)}
-impl_unary_cli! {<feature::BatteryCare><BatteryCare>("Enable or disable battery care", "", command::set_battery_care, command::get_battery_care)}
+impl_unary_cli! {<feature::BatteryCare><BatteryCare>("Enable or disable battery care", "", command::set_battery_care, command::get_battery_care,
+battery_care_summary)}
 impl_unary_cli! {<feature::LidLogo><LogoMode>("Set lid logo mode", "", command::set_logo_mode, command::get_logo_mode)}
-impl_unary_cli! {<feature::LightsAlwaysOn><LightsAlwaysOn>("Set lights always on", "", command::set_lights_always_on, command::get_lights_always_on)}
+
+// e.g. "Enable, stops charging at 80%, battery at 80% on AC, limiting charging now"
+fn battery_care_summary(device: &device::Device, care: BatteryCare) -> String {
+    let mut summary = format!("{:?}", care);
+    let threshold = command::get_battery_care_threshold(device);
+    if let Ok(threshold) = threshold {
+        summary += &format!(", stops charging at {}%", threshold);
+    }
+    match command::get_battery_status(device) {
+        Ok(status) => {
+            summary += &format!(
+                ", battery at {}% {}",
+                status.level_pct,
+                match status.plugged_in {
+                    true => "on AC",
+                    false => "on battery",
+                }
+            );
+            let limiting = care == BatteryCare::Enable
+                && status.plugged_in
+                && threshold.is_ok_and(|threshold| status.level_pct >= threshold);
+            if limiting {
+                summary += ", limiting charging now";
+            }
+        }
+        Err(e) => debug!("Battery status: {}", e),
+    }
+    summary
+}
+
+// Set by Ctrl-C, waking every loop of `run_until_interrupted`
+static INTERRUPTED: (std::sync::Mutex<bool>, std::sync::Condvar) =
+    (std::sync::Mutex::new(false), std::sync::Condvar::new());
+
+fn interrupt() {
+    *INTERRUPTED
+        .0
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner) = true;
+    INTERRUPTED.1.notify_all();
+}
+
+// The handler can only be set once per process, while loops nest, e.g. `--every` around
+// `fan curve`
+fn ensure_ctrlc_handler() -> Result<()> {
+    static HANDLER: std::sync::OnceLock<Result<(), String>> = std::sync::OnceLock::new();
+    HANDLER
+        .get_or_init(|| ctrlc::set_handler(interrupt).map_err(|e| e.to_string()))
+        .clone()
+        .map_err(|e| anyhow::anyhow!("Failed to set the Ctrl-C handler: {}", e))
+}
+
+// Calls `tick` every `interval` until Ctrl-C is pressed, which ends enclosing loops too
+fn run_until_interrupted(
+    interval: std::time::Duration,
+    mut tick: impl FnMut() -> Result<()>,
+) -> Result<()> {
+    ensure_ctrlc_handler()?;
+    let (interrupted, wake) = &INTERRUPTED;
+    let lock = || {
+        interrupted
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    };
+    loop {
+        if *lock() {
+            return Ok(());
+        }
+        tick()?;
+        let (stop, _) = wake
+            .wait_timeout_while(lock(), interval, |stop| !*stop)
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if *stop {
+            return Ok(());
+        }
+    }
+}
+
+impl Cli for feature::LightsAlwaysOn {
+    fn cmd(&self) -> Option<Command> {
+        Some(
+            clap::Command::new(self.name())
+                .about("Set lights always on")
+                .arg(arg!(<ARG> "").value_parser(clap::value_parser!(LightsAlwaysOn)))
+                .subcommand(
+                    clap::Command::new("daemon")
+                        .about("Keep the keyboard lit by periodically re-sending its brightness")
+                        .arg(
+                            arg!(-i --interval <SECONDS> "Seconds between refreshes")
+                                .default_value("30")
+                                .value_parser(clap::value_parser!(u64).range(1..)),
+                        ),
+                )
+                .args_conflicts_with_subcommands(true)
+                .subcommand_negates_reqs(true)
+                .arg_required_else_help(true),
+        )
+    }
+
+    fn handle(
+        &self,
+        device: &device::Device,
+        matches: &clap::ArgMatches,
+        _config: &config::Config,
+    ) -> Result<()> {
+        match matches.subcommand() {
+            Some((ident, matches)) if ident == self.name() => match matches.subcommand() {
+                Some(("daemon", matches)) => {
+                    let interval = *matches.get_one::<u64>("interval").unwrap();
+                    let _lock = lock::acquire(device.info.pid)?;
+                    let previous = command::get_lights_always_on(device)?;
+                    command::set_lights_always_on(device, LightsAlwaysOn::Enable)?;
+                    self.notify(
+                        device,
+                        &format!("Keeping lights on, refreshing every {} seconds", interval),
+                    );
+
+                    let result =
+                        run_until_interrupted(std::time::Duration::from_secs(interval), || {
+                            // re-send whatever is set now so user changes are preserved
+                            let brightness = command::get_keyboard_brightness(device)?;
+                            command::set_keyboard_brightness(device, brightness)?;
+                            debug!("Keyboard brightness refreshed at {}", brightness);
+                            Ok(())
+                        });
+
+                    command::set_lights_always_on(device, previous)?;
+                    self.notify(
+                        device,
+                        &format!("lights always on restored to {:?}", previous),
+                    );
+                    result
+                }
+                _ => {
+                    let arg = matches.get_one::<LightsAlwaysOn>("ARG").unwrap();
+                    write_setting(
+                        matches,
+                        device,
+                        *arg,
+                        command::set_lights_always_on,
+                        command::get_lights_always_on,
+                    )?;
+                    verify(matches, *arg, || command::get_lights_always_on(device))?;
+                    self.notify(
+                        device,
+                        &format!("{} set to {:?}", self.name().replace('-', " "), arg),
+                    );
+                    Ok(())
+                }
+            },
+            Some(("info", _)) => {
+                info!(
+                    "{}: {:?}",
+                    self.name(),
+                    command::get_lights_always_on(device)?
+                );
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn brightness_pct(brightness: u8) -> u32 {
+    (brightness as u32 * 100 + 127) / 255
+}
+
+impl Cli for feature::KbdBacklight {
+    fn cmd(&self) -> Option<Command> {
+        Some(
+            clap::Command::new(self.name())
+                .about("Set keyboard backlight brightness")
+                .arg(arg!(<ARG> "Number in range [0, 255]").value_parser(clap::value_parser!(u8)))
+                .subcommand(
+                    clap::Command::new("toggle").about(
+                        "Turn the backlight off, or back on at the brightness it had before",
+                    ),
+                )
+                .after_help("Examples:\n  razer-cli auto kbd-backlight 128\n  razer-cli auto kbd-backlight toggle")
+                .args_conflicts_with_subcommands(true)
+                .subcommand_negates_reqs(true)
+                .arg_required_else_help(true),
+        )
+    }
+
+    fn handle(
+        &self,
+        device: &device::Device,
+        matches: &clap::ArgMatches,
+        _config: &config::Config,
+    ) -> Result<()> {
+        match matches.subcommand() {
+            Some((ident, matches)) if ident == self.name() => match matches.subcommand() {
+                Some(("toggle", _)) => {
+                    let path = config::last_brightness_path(device.info.pid)?;
+                    let current = command::get_keyboard_brightness(device)?;
+                    if current > 0 {
+                        // remembered across runs, each toggle is a separate process
+                        std::fs::create_dir_all(path.parent().unwrap())?;
+                        std::fs::write(&path, current.to_string())?;
+                        command::set_keyboard_brightness(device, 0)?;
+                        self.notify(device, "Keyboard backlight off");
+                        return Ok(());
+                    }
+                    let restored = std::fs::read_to_string(&path)
+                        .ok()
+                        .and_then(|s| s.trim().parse::<u8>().ok())
+                        .filter(|&brightness| brightness > 0)
+                        .unwrap_or(u8::MAX);
+                    command::set_keyboard_brightness(device, restored)?;
+                    self.notify(
+                        device,
+                        &format!(
+                            "Keyboard backlight on at {} ({}%)",
+                            restored,
+                            brightness_pct(restored)
+                        ),
+                    );
+                    Ok(())
+                }
+                _ => {
+                    let arg = matches.get_one::<u8>("ARG").unwrap();
+                    write_setting(
+                        matches,
+                        device,
+                        *arg,
+                        command::set_keyboard_brightness,
+                        command::get_keyboard_brightness,
+                    )?;
+                    verify(matches, *arg, || command::get_keyboard_brightness(device))?;
+                    self.notify(
+                        device,
+                        &format!("{} set to {:?}", self.name().replace('-', " "), arg),
+                    );
+                    Ok(())
+                }
+            },
+            Some(("info", _)) => {
+                let brightness = command::get_keyboard_brightness(device)?;
+                info!(
+                    "{}: {} ({}%)",
+                    self.name(),
+                    brightness,
+                    brightness_pct(brightness)
+                );
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
 
 struct CustomCommand;
 
@@ -103,73 +451,283 @@ impl Cli for CustomCommand {
                         .required(true)
                         .value_parser(clap_num::maybe_hex::<u16>),
                 )
+                .arg(
+                    arg!(--sweep <RANGE> "Send the command once per value in RANGE, e.g. 0..256, appended after ARGS")
+                        .value_parser(parse_sweep),
+                )
+                .arg(
+                    arg!(--delay <MS> "Milliseconds between sweep iterations")
+                        .default_value("10")
+                        .requires("sweep")
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(arg!(-y --yes "Don't ask before sweeping").requires("sweep"))
                 .arg(
                     arg!(<ARGS>... "Arguments to the command, e.g. 0 1 3 5")
                         .required(false)
                         .trailing_var_arg(true)
                         .value_parser(clap_num::maybe_hex::<u8>),
                 )
+                .subcommand(
+                    clap::Command::new("run")
+                        .about("Run a command aliased in the config file")
+                        .arg(arg!(<ALIAS> "Name of the alias, see `cmd list`"))
+                        .arg_required_else_help(true),
+                )
+                .subcommand(
+                    clap::Command::new("list").about("List the aliases of the config file"),
+                )
+                .subcommand_negates_reqs(true)
+                .args_conflicts_with_subcommands(true)
+                .after_help("Example, reading the performance mode of fan zone 1:\n  razer-cli auto cmd 0x0d82 0 1 0 0\n\nAliases are defined in config.toml:\n  [aliases.perf-zone1]\n  command = 0x0d82\n  args = [0, 1, 0, 0]\n  description = \"Performance mode of fan zone 1\"")
                 .arg_required_else_help(true),
         )
     }
-    fn handle(&self, device: &device::Device, matches: &clap::ArgMatches) -> Result<()> {
+    fn handle(
+        &self,
+        device: &device::Device,
+        matches: &clap::ArgMatches,
+        config: &config::Config,
+    ) -> Result<()> {
         match matches.subcommand() {
             Some((ident, matches)) if ident == self.name() => {
+                match matches.subcommand() {
+                    Some(("list", _)) => return list_aliases(config),
+                    Some(("run", matches)) => {
+                        return self.run_alias(
+                            device,
+                            config,
+                            matches.get_one::<String>("ALIAS").unwrap(),
+                        )
+                    }
+                    _ => (),
+                }
                 let cmd = *matches.get_one::<u16>("COMMAND").unwrap();
-                let args: Vec<u8> = matches.get_many::<u8>("ARGS").unwrap().copied().collect();
+                let args: Vec<u8> = matches
+                    .get_many::<u8>("ARGS")
+                    .map_or_else(Vec::new, |args| args.copied().collect());
+                if matches.contains_id("sweep") {
+                    return self.sweep(device, cmd, &args, matches);
+                }
                 debug!("Running custom command: {:x?} {:?}", cmd, args);
-                self.notify("Custom command executed successfully");
-                command::custom_command(device, cmd, &args)
+                self.notify(device, "Custom command executed successfully");
+                command::custom_command(device, cmd, &args).map(|_| ())
             }
             _ => Ok(()),
         }
     }
 }
 
-impl Cli for feature::Fan {
+// `start..end` with an exclusive end of at most 256, either bound may be hex
+fn parse_sweep(value: &str) -> Result<std::ops::Range<u16>, String> {
+    let (start, end) = value
+        .split_once("..")
+        .ok_or_else(|| format!("expected START..END, got {}", value))?;
+    let range = clap_num::maybe_hex::<u16>(start)?..clap_num::maybe_hex::<u16>(end)?;
+    if range.is_empty() || range.end > 0x100 {
+        return Err(format!("{} must be a non-empty range within 0..256", value));
+    }
+    Ok(range)
+}
+
+fn list_aliases(config: &config::Config) -> Result<()> {
+    let aliases = &config.aliases;
+    if aliases.is_empty() {
+        info!("No aliases, add them to config.toml as [aliases.<name>], see `cmd --help`");
+    }
+    for (name, alias) in aliases {
+        info!(
+            "{}: {:#06x} {:02x?}{}",
+            name,
+            alias.command,
+            alias.args,
+            alias
+                .description
+                .as_ref()
+                .map_or_else(String::new, |description| format!("  {}", description))
+        );
+    }
+    Ok(())
+}
+
+impl CustomCommand {
+    fn run_alias(
+        &self,
+        device: &device::Device,
+        config: &config::Config,
+        name: &str,
+    ) -> Result<()> {
+        let alias = config
+            .aliases
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("No alias {} in config.toml, see `cmd list`", name))?;
+        debug!(
+            "Running alias {}: {:#06x} {:?}",
+            name, alias.command, alias.args
+        );
+        let response = command::custom_command(device, alias.command, &alias.args)?;
+        info!("{}: {:02x?}", name, response.get_args());
+        self.notify(device, &format!("Alias {} executed successfully", name));
+        Ok(())
+    }
+
+    // Unknown values may change settings or upset the controller, so the user confirms first
+    fn sweep(
+        &self,
+        device: &device::Device,
+        cmd: u16,
+        args: &[u8],
+        matches: &clap::ArgMatches,
+    ) -> Result<()> {
+        let range = matches
+            .get_one::<std::ops::Range<u16>>("sweep")
+            .unwrap()
+            .clone();
+        let delay = std::time::Duration::from_millis(*matches.get_one::<u64>("delay").unwrap());
+        let question = format!(
+            "Send command {:#06x} {} times with unknown arguments?",
+            cmd,
+            range.len()
+        );
+        anyhow::ensure!(
+            matches.get_flag("yes") || confirm(&question)?,
+            "Sweep cancelled, pass --yes to run it without a terminal"
+        );
+        for value in range {
+            let args: Vec<u8> = args.iter().copied().chain([value as u8]).collect();
+            match command::custom_command(device, cmd, &args) {
+                Ok(response) => info!("{:#04x}: {:02x?}", value, response.get_args()),
+                Err(e) => warn!("{:#04x}: {}", value, e),
+            }
+            std::thread::sleep(delay);
+        }
+        Ok(())
+    }
+}
+
+struct Profile;
+
+impl Feature for Profile {
+    fn name(&self) -> &'static str {
+        "apply"
+    }
+}
+
+// With --format json or --skip-unchanged, reads every feature first and reports which were sent
+fn apply_profile(
+    matches: &clap::ArgMatches,
+    device: &device::Device,
+    profile: &serde_json::Map<String, serde_json::Value>,
+) -> Result<Option<feature::Applied>> {
+    if !(json_format(matches) || matches.get_flag("skip-unchanged")) {
+        return feature::apply(device, profile).map(|()| None);
+    }
+    let applied = feature::apply_if_changed(device, profile)?;
+    debug!("Already set, not sent: {:?}", applied.unchanged);
+    Ok(Some(applied))
+}
+
+fn json_format(matches: &clap::ArgMatches) -> bool {
+    matches
+        .get_one::<String>("format")
+        .is_some_and(|format| format == "json")
+}
+
+impl Profile {
+    fn apply_to_all(
+        &self,
+        matches: &clap::ArgMatches,
+        config: &device::DeviceConfig,
+        profile: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<()> {
+        let devices = device::Device::list()?;
+        anyhow::ensure!(
+            !devices.is_empty(),
+            librazer::Error::DeviceNotFound("No supported Razer devices found".into())
+        );
+        let total = devices.len();
+        let mut failed = 0;
+        let mut summaries = Vec::new();
+        // keep going past a failing device, the summary decides the exit code
+        for (descriptor, device) in devices {
+            let summary = serde_json::json!({ "device": descriptor.name, "pid": descriptor.pid });
+            match device.and_then(|mut device| {
+                device.set_config(config.clone());
+                apply_profile(matches, &device, profile)
+            }) {
+                Ok(applied) => {
+                    info!("{} ({:#06x}): applied", descriptor.name, descriptor.pid);
+                    summaries.push(merge(summary, serde_json::to_value(applied)?));
+                }
+                Err(e) => {
+                    failed += 1;
+                    error!("{} ({:#06x}): {:#}", descriptor.name, descriptor.pid, e);
+                    summaries.push(merge(
+                        summary,
+                        serde_json::json!({ "error": format!("{:#}", e) }),
+                    ));
+                }
+            }
+        }
+        if json_format(matches) {
+            write_output(matches, &serde_json::to_string(&summaries)?)?;
+        }
+        anyhow::ensure!(
+            failed == 0,
+            "Profile failed on {} of {} devices",
+            failed,
+            total
+        );
+        // spans several devices, so there's no single name to prefix
+        notify::send(&format!("Profile applied to {} devices", total));
+        Ok(())
+    }
+}
+
+// Fields of the `extra` object added to the `base` object
+fn merge(mut base: serde_json::Value, extra: serde_json::Value) -> serde_json::Value {
+    if let (Some(base), serde_json::Value::Object(extra)) = (base.as_object_mut(), extra) {
+        base.extend(extra);
+    }
+    base
+}
+
+impl Cli for Profile {
     fn cmd(&self) -> Option<Command> {
         Some(
             clap::Command::new(self.name())
-                .about("Control fan")
-                .subcommand(clap::Command::new("auto").about("Set fan mode to auto"))
-                .subcommand(clap::Command::new("manual").about("Set fan mode to manual"))
-                .subcommand(impl_unary_cmd_cli!{{clap::value_parser!(u16).range(2000..=5000)}, "rpm", "RPM", "Set fan rpm", "Fan RPM in range [2000, 5000]"})
-                .subcommand(impl_unary_cmd_cli!{{clap::value_parser!(MaxFanSpeedMode)}, "max", "MAX", "Control Max Fan Speed Mode", "Max Fan Speed Mode"})
+                .about("Apply a JSON profile mapping feature names to values, e.g. {\"perf\": {\"mode\": \"Silent\"}}")
+                .after_help("With --format json the features are read first, only those that differ are sent, and {\"changed\": [...], \"unchanged\": [...]} is printed, one object per device with --all-devices.")
+                .arg(
+                    arg!(<PROFILE> "Path to the profile")
+                        .value_parser(clap::value_parser!(std::path::PathBuf)),
+                )
+                .arg(arg!(--"all-devices" "Apply to every connected supported device and report each"))
                 .arg_required_else_help(true),
         )
     }
-
-    fn handle(&self, device: &device::Device, matches: &clap::ArgMatches) -> Result<()> {
+    fn handle(
+        &self,
+        device: &device::Device,
+        matches: &clap::ArgMatches,
+        config: &config::Config,
+    ) -> Result<()> {
         match matches.subcommand() {
             Some((ident, matches)) if ident == self.name() => {
-                if let Some(_) = matches.subcommand_matches("auto") {
-                    command::set_fan_mode(device, FanMode::Auto)?;
-                    self.notify("Fan mode set to Auto");
-                }
-                if let Some(_) = matches.subcommand_matches("manual") {
-                    command::set_fan_mode(device, FanMode::Manual)?;
-                    self.notify("Fan mode set to Manual");
+                let path = matches.get_one::<std::path::PathBuf>("PROFILE").unwrap();
+                let profile: serde_json::Map<String, serde_json::Value> =
+                    serde_json::from_str(&std::fs::read_to_string(path)?)?;
+                if profile_overclocks(&profile) {
+                    battery_guard(matches, device, config, "CPU overclock")?;
                 }
-                if let Some(rpm_matches) = matches.subcommand_matches("rpm") {
-                    let rpm = *rpm_matches.get_one::<u16>("RPM").unwrap();
-                    command::set_fan_rpm(device, rpm)?;
-                    self.notify(&format!("Fan RPM set to {}", rpm));
+                if matches.get_flag("all-devices") {
+                    return self.apply_to_all(matches, device.config(), &profile);
                 }
-                impl_unary_handle_cli! {<MaxFanSpeedMode>(matches, device, "max", "MAX", command::set_max_fan_speed_mode)}
-                Ok(())
-            }
-            Some(("info", _)) => {
-                match command::get_perf_mode(device) {
-                    Ok((_, fan_mode @ FanMode::Auto)) => info!("Fan: {:?}", fan_mode),
-                    Ok((_, fan_mode @ FanMode::Manual)) => {
-                        info!(
-                            "Fan: {:?}@{:?} RPM",
-                            fan_mode,
-                            command::get_fan_rpm(device, FanZone::Zone1)?
-                        );
-                    }
-                    Err(e) => error!("{}", e),
+                let applied = apply_profile(matches, device, &profile)?;
+                if let (true, Some(applied)) = (json_format(matches), applied) {
+                    write_output(matches, &serde_json::to_string(&applied)?)?;
                 }
+                self.notify(device, &format!("Profile {} applied", path.display()));
                 Ok(())
             }
             _ => Ok(()),
@@ -177,52 +735,92 @@ impl Cli for feature::Fan {
     }
 }
 
-impl Cli for feature::Perf {
+// Feature added at runtime with `feature::register`, driven through its generic accessors
+struct RegisteredFeature(&'static feature::FeatureInfo);
+
+impl Feature for RegisteredFeature {
+    fn name(&self) -> &'static str {
+        self.0.name
+    }
+}
+
+impl Cli for RegisteredFeature {
     fn cmd(&self) -> Option<Command> {
         Some(
             clap::Command::new(self.name())
-                .about("Control performance modes")
-                .subcommand(impl_unary_cmd_cli!{{clap::value_parser!(PerfMode)}, "mode", "MODE", "Set performance mode", "Performance mode"})
-                .subcommand(impl_unary_cmd_cli!{{clap::value_parser!(CpuBoost)}, "cpu", "CPU", "Set CPU boost", "CPU boost"})
-                .subcommand( impl_unary_cmd_cli!{{clap::value_parser!(GpuBoost)}, "gpu", "GPU", "Set GPU boost", "GPU boost"})
+                .about(self.0.description)
+                .arg(arg!(<VALUE> "Value as JSON, bare words are taken as strings"))
                 .arg_required_else_help(true),
         )
     }
-
-    fn handle(&self, device: &device::Device, matches: &clap::ArgMatches) -> Result<()> {
+    fn handle(
+        &self,
+        device: &device::Device,
+        matches: &clap::ArgMatches,
+        _config: &config::Config,
+    ) -> Result<()> {
         match matches.subcommand() {
             Some((ident, matches)) if ident == self.name() => {
-                if let Some(mode_matches) = matches.subcommand_matches("mode") {
-                    let old_mode = command::get_perf_mode(device)?.0;
-                    let new_mode = *mode_matches.get_one::<PerfMode>("MODE").unwrap();
-                    command::set_perf_mode(device, new_mode)?;
-                    self.notify(&format!(
-                        "Performance mode changed from {:?} to {:?}",
-                        old_mode, new_mode
-                    ));
-                }
-                impl_unary_handle_cli! {<CpuBoost>(matches, device, "cpu", "CPU", command::set_cpu_boost)}
-                impl_unary_handle_cli! {<GpuBoost>(matches, device, "gpu", "GPU", command::set_gpu_boost)}
+                let arg = matches.get_one::<String>("VALUE").unwrap();
+                let value = serde_json::from_str(arg)
+                    .unwrap_or_else(|_| serde_json::Value::String(arg.clone()));
+                self.0.set(device, value.clone())?;
+                verify(matches, value, || self.0.get(device))?;
+                self.notify(device, &format!("{} set to {}", self.name(), arg));
                 Ok(())
             }
             Some(("info", _)) => {
-                let perf_mode = command::get_perf_mode(device);
-                info!("Performance: {:?}", perf_mode);
-                if let Ok((PerfMode::Custom, _)) = perf_mode {
-                    let cpu_boost = command::get_cpu_boost(device);
-                    let gpu_boost = command::get_gpu_boost(device);
-                    info!("CPU: {:?}", cpu_boost);
-                    info!("GPU: {:?}", gpu_boost);
+                info!("{}: {}", self.name(), raw_value(&self.0.get(device)?));
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
 
-                    if let (Ok(CpuBoost::Boost) | Ok(CpuBoost::Overclock), Ok(GpuBoost::High)) =
-                        (cpu_boost, gpu_boost)
-                    {
-                        info!(
-                            "Max Fan Speed: {:?}",
-                            command::get_max_fan_speed_mode(device)?
-                        );
+struct SaveBootProfile;
+
+impl Feature for SaveBootProfile {
+    fn name(&self) -> &'static str {
+        "save-boot-profile"
+    }
+}
+
+impl Cli for SaveBootProfile {
+    fn cmd(&self) -> Option<Command> {
+        Some(clap::Command::new(self.name()).about(
+            "Save the current settings as a profile for `apply-boot-profile` to restore at login",
+        ))
+    }
+    fn handle(
+        &self,
+        device: &device::Device,
+        matches: &clap::ArgMatches,
+        _config: &config::Config,
+    ) -> Result<()> {
+        match matches.subcommand() {
+            Some((ident, _)) if ident == self.name() => {
+                let mut profile = serde_json::Map::new();
+                for name in device.info.features {
+                    let Ok(info) = feature::find(name) else {
+                        continue;
+                    };
+                    match info.get(device) {
+                        Ok(value) => {
+                            profile.insert(name.to_string(), value);
+                        }
+                        Err(e) => warn!("Not saving {}: {}", name, e),
                     }
                 }
+                let path = match matches.get_one::<std::path::PathBuf>("output") {
+                    Some(path) => path.clone(),
+                    None => config::boot_profile_path()?,
+                };
+                config::write_atomic(&path, &serde_json::to_string_pretty(&profile)?)?;
+                self.notify(
+                    device,
+                    &format!("Saved {} settings to {}", profile.len(), path.display()),
+                );
                 Ok(())
             }
             _ => Ok(()),
@@ -230,121 +828,1949 @@ impl Cli for feature::Perf {
     }
 }
 
-fn enumerate() -> Result<()> {
-    match device::Device::enumerate() {
-        Ok((pid_list, model_number_prefix)) => {
-            info!("Model: {}", model_number_prefix);
-            
-            let supported = SUPPORTED.iter().any(|d| 
-                model_number_prefix.starts_with(d.model_number_prefix)
-            );
-            
-            info!("Supported: {}", supported);
-            info!("PID: {:#06x?}", pid_list);
-            Ok(())
-        }
-        Err(e) => {
-            eprintln!("Enumeration failed: {}", e);
-            Err(e)
-        }
+struct ApplyBootProfile;
+
+impl Feature for ApplyBootProfile {
+    fn name(&self) -> &'static str {
+        "apply-boot-profile"
     }
 }
 
-fn update_cmd(cmd: Command, features: &[Box<dyn Cli>]) -> Command {
-    features
-        .iter()
-        .filter_map(|f| f.cmd())
-        .fold(cmd, |cmd, f| cmd.subcommand(f))
+impl Cli for ApplyBootProfile {
+    fn cmd(&self) -> Option<Command> {
+        Some(
+            clap::Command::new(self.name())
+                .about("Restore the settings saved by `save-boot-profile`, e.g. from a login unit"),
+        )
+    }
+    fn handle(
+        &self,
+        device: &device::Device,
+        matches: &clap::ArgMatches,
+        config: &config::Config,
+    ) -> Result<()> {
+        match matches.subcommand() {
+            Some((ident, matches)) if ident == self.name() => {
+                let path = config::boot_profile_path()?;
+                let contents = std::fs::read_to_string(&path).with_context(|| {
+                    format!(
+                        "Failed to read {}, run save-boot-profile first",
+                        path.display()
+                    )
+                })?;
+                let profile: serde_json::Map<String, serde_json::Value> =
+                    serde_json::from_str(&contents)?;
+                if profile_overclocks(&profile) {
+                    battery_guard(matches, device, config, "CPU overclock")?;
+                }
+                feature::apply(device, &profile)?;
+                self.notify(
+                    device,
+                    &format!("Settings restored from {}", path.display()),
+                );
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
-fn handle(
-    device: &device::Device,
-    matches: &clap::ArgMatches,
-    features: &Vec<Box<dyn Cli>>,
-) -> Result<()> {
-    if let Some(("info", _)) = matches.subcommand() {
-        info!("Device: {:?}", device.info);
-    }
+struct Dashboard;
 
-    for f in features {
-        f.handle(device, matches)?;
+impl Feature for Dashboard {
+    fn name(&self) -> &'static str {
+        "tui"
     }
-    Ok(())
 }
 
-fn gen_cli_features(feature_list: &[&str]) -> Vec<Box<dyn Cli>> {
-    use feature::*;
-    librazer::iter_features!(|_, feature| -> Box<dyn Cli> { Box::new(feature) })
-        .into_iter()
-        .filter(|f| feature_list.contains(&f.name()))
-        .collect()
+impl Cli for Dashboard {
+    fn cmd(&self) -> Option<Command> {
+        Some(clap::Command::new(self.name()).about("Interactive dashboard refreshed every second"))
+    }
+    fn handle(
+        &self,
+        device: &device::Device,
+        matches: &clap::ArgMatches,
+        _config: &config::Config,
+    ) -> Result<()> {
+        match matches.subcommand() {
+            Some((ident, _)) if ident == self.name() => tui::run(device),
+            _ => Ok(()),
+        }
+    }
 }
 
-fn main() -> Result<()> {
-    // Initialize logging FIRST
-    env_logger::Builder::new()
-        .filter_level(log::LevelFilter::Info)
-        .format_timestamp(None)
-        .init();
+struct Serve;
 
-    let info_cmd = clap::Command::new("info").about("Get device info");
-    let auto_cmd = clap::Command::new("auto")
-        .about("Automatically detect supported Razer device and enable device specific features")
-        .subcommand(info_cmd.clone())
-        .subcommand_required(true);
+impl Feature for Serve {
+    fn name(&self) -> &'static str {
+        "serve"
+    }
+}
 
-    let manual_cmd =clap::Command::new("manual").about("Manually specify PID of the Razer device and enable all features (many might not work)")
-            .arg(
+impl Cli for Serve {
+    fn cmd(&self) -> Option<Command> {
+        let socket = arg!(--socket <PATH> "Socket to listen on, a named pipe on Windows")
+            .value_parser(clap::value_parser!(std::path::PathBuf));
+        #[cfg(windows)]
+        let socket = socket.default_value(server::DEFAULT_PIPE);
+        #[cfg(not(windows))]
+        let socket = socket.required(true);
+        Some(
+            clap::Command::new(self.name())
+                .about("Keep the device open and answer line-delimited JSON requests on a Unix socket or a Windows named pipe")
+                .arg(socket)
+                .after_help("Example, see razer-cli/examples/serve-client.py for a client:\n  razer-cli auto serve --socket /tmp/razer.sock\n  echo '{\"cmd\": \"set_fan_rpm\", \"args\": {\"rpm\": 3200}}' | socat - UNIX-CONNECT:/tmp/razer.sock"),
+        )
+    }
+    fn handle(
+        &self,
+        device: &device::Device,
+        matches: &clap::ArgMatches,
+        _config: &config::Config,
+    ) -> Result<()> {
+        match matches.subcommand() {
+            Some((ident, matches)) if ident == self.name() => {
+                let _lock = lock::acquire(device.info.pid)?;
+                server::run(
+                    device,
+                    matches.get_one::<std::path::PathBuf>("socket").unwrap(),
+                )
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+struct Benchmark;
+
+impl Feature for Benchmark {
+    fn name(&self) -> &'static str {
+        "benchmark"
+    }
+}
+
+impl Cli for Benchmark {
+    fn cmd(&self) -> Option<Command> {
+        Some(
+            clap::Command::new(self.name())
+                .about("Measure HID round trip latency and suggest --pre-write-delay and --post-write-delay values")
+                .hide(true)
+                .arg(
+                    arg!(--count <N> "Getter calls per measurement")
+                        .default_value("200")
+                        .value_parser(clap::value_parser!(u32).range(1..)),
+                ),
+        )
+    }
+    fn handle(
+        &self,
+        device: &device::Device,
+        matches: &clap::ArgMatches,
+        _config: &config::Config,
+    ) -> Result<()> {
+        match matches.subcommand() {
+            Some((ident, matches)) if ident == self.name() => {
+                let _lock = lock::acquire(device.info.pid)?;
+                benchmark::run(device, *matches.get_one::<u32>("count").unwrap() as usize)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// approximate duration of one `Device::send`, bounds the animation rate
+const FRAME_SEND_MS: u32 = 3;
+
+impl Cli for feature::KbdRgb {
+    fn cmd(&self) -> Option<Command> {
+        Some(
+            clap::Command::new(self.name())
+                .about("Control per-key keyboard colors")
+                .subcommand(
+                    clap::Command::new("load")
+                        .about("Load per-key colors from a JSON layout, one array of \"#rrggbb\" per keyboard row")
+                        .arg(
+                            arg!(<LAYOUT> "Path to the layout file")
+                                .value_parser(clap::value_parser!(std::path::PathBuf)),
+                        )
+                        .arg_required_else_help(true),
+                )
+                .subcommand(
+                    clap::Command::new("animate")
+                        .about("Loop through a JSON array of layouts until interrupted")
+                        .arg(
+                            arg!(<FRAMES> "Path to the frames file")
+                                .value_parser(clap::value_parser!(std::path::PathBuf)),
+                        )
+                        .arg(
+                            arg!(--fps <FPS> "Frames per second")
+                                .default_value("10")
+                                .value_parser(clap::value_parser!(u32).range(1..)),
+                        )
+                        .arg(
+                            arg!(--restore <COLOR> "Static color to set on exit")
+                                .default_value("#ffffff")
+                                .value_parser(clap::value_parser!(Rgb)),
+                        )
+                        .arg_required_else_help(true),
+                )
+                .arg_required_else_help(true),
+        )
+    }
+
+    fn handle(
+        &self,
+        device: &device::Device,
+        matches: &clap::ArgMatches,
+        _config: &config::Config,
+    ) -> Result<()> {
+        match matches.subcommand() {
+            Some((ident, matches)) if ident == self.name() => {
+                if let Some(animate_matches) = matches.subcommand_matches("animate") {
+                    let path = animate_matches
+                        .get_one::<std::path::PathBuf>("FRAMES")
+                        .unwrap();
+                    let fps = *animate_matches.get_one::<u32>("fps").unwrap();
+                    let restore = *animate_matches.get_one::<Rgb>("restore").unwrap();
+                    let frames: Vec<Vec<Vec<Rgb>>> =
+                        serde_json::from_str(&std::fs::read_to_string(path)?)?;
+                    anyhow::ensure!(!frames.is_empty(), "{} has no frames", path.display());
+                    let _lock = lock::acquire(device.info.pid)?;
+
+                    // every frame costs one send per row plus the effect switch
+                    let rows = device.info.key_matrix.map_or(0, |(rows, _)| rows) as u32;
+                    let max_fps = 1000 / (FRAME_SEND_MS * (rows + 1));
+                    anyhow::ensure!(
+                        fps <= max_fps,
+                        "{} fps is too high, {} can do at most {}",
+                        fps,
+                        device.info.name,
+                        max_fps
+                    );
+
+                    self.notify(
+                        device,
+                        &format!(
+                            "Playing {} frames at {} fps, press Ctrl-C to stop",
+                            frames.len(),
+                            fps
+                        ),
+                    );
+                    let mut frames = frames.iter().cycle();
+                    let result =
+                        run_until_interrupted(std::time::Duration::from_secs(1) / fps, || {
+                            command::set_per_key_colors(device, frames.next().unwrap())
+                        });
+
+                    command::set_static_color(device, restore)?;
+                    self.notify(
+                        device,
+                        &format!("Keyboard color restored to {}", String::from(restore)),
+                    );
+                    result?;
+                }
+                if let Some(load_matches) = matches.subcommand_matches("load") {
+                    let path = load_matches
+                        .get_one::<std::path::PathBuf>("LAYOUT")
+                        .unwrap();
+                    let frame: Vec<Vec<Rgb>> =
+                        serde_json::from_str(&std::fs::read_to_string(path)?)?;
+                    command::set_per_key_colors(device, &frame)?;
+                    self.notify(
+                        device,
+                        &format!("Keyboard colors loaded from {}", path.display()),
+                    );
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum RpmChange {
+    To(u16),
+    // relative to the current RPM, clamped to the limits
+    By(i32),
+}
+
+// "3500" sets the RPM, "+500" and "-500" change it
+fn parse_rpm(value: &str) -> Result<RpmChange, String> {
+    let invalid = |_| format!("expected an RPM such as 3500, +500 or -500, got {}", value);
+    match value.starts_with(['+', '-']) {
+        true => value.parse().map(RpmChange::By).map_err(invalid),
+        false => value.parse().map(RpmChange::To).map_err(invalid),
+    }
+}
+
+// The daemon behind `fan curve`, `fan` only sends the notifications
+// `fan curve <CURVE>`: a curve file when one exists at CURVE, inline points otherwise
+fn curve_config(matches: &clap::ArgMatches) -> Result<curve::CurveConfig> {
+    let curve = matches.get_one::<String>("CURVE").unwrap();
+    let path = std::path::Path::new(curve);
+    if path.is_file() {
+        return curve::CurveConfig::load(path);
+    }
+    let temp_source = match (
+        matches.get_one::<std::path::PathBuf>("hwmon"),
+        matches.get_one::<String>("temp-command"),
+    ) {
+        (Some(path), _) => curve::TempSource::Hwmon(path.clone()),
+        (None, Some(command)) => curve::TempSource::Command(command.clone()),
+        (None, None) => anyhow::bail!(
+            "{} is not a curve file, and inline points need --hwmon or --temp-command to read the temperature",
+            curve
+        ),
+    };
+    curve::CurveConfig::inline(
+        curve,
+        temp_source,
+        matches.get_one::<u64>("interval").copied(),
+    )
+}
+
+fn follow_curve(
+    fan: &feature::Fan,
+    device: &device::Device,
+    config: &curve::CurveConfig,
+    unit: config::TemperatureUnit,
+) -> Result<()> {
+    let _lock = lock::acquire(device.info.pid)?;
+    let (min, max) = command::get_fan_rpm_limits(device)?;
+    // checked before the first tick, not when the mode with a bad curve comes up
+    config.ensure_within((min, max))?;
+    let step = command::get_fan_rpm_step(device)?;
+    fan.notify(
+        device,
+        &format!(
+            "Following the fan curve, reading every {} seconds",
+            config.interval
+        ),
+    );
+
+    let mut current = None;
+    // mode of the last tick, a change picks the curve again
+    let mut mode = None;
+    let result = run_until_interrupted(
+        std::time::Duration::from_secs(config.interval.max(1)),
+        || {
+            // manual RPM only exists in balanced and turbo mode, in the others the firmware
+            // runs its own curve for the mode, including max fan speed in custom mode
+            let state = command::get_perf_state(device)?;
+            let curve = config.curve_for(state.perf_mode);
+            if mode != Some(state.perf_mode) {
+                mode = Some(state.perf_mode);
+                current = None;
+                match curve {
+                    Some(_) => fan.notify(
+                        device,
+                        &format!("Following the {:?} fan curve", state.perf_mode),
+                    ),
+                    None => {
+                        // a previous mode's curve may have left the fan at its last RPM
+                        if state.perf_mode.takes_manual_fan() && state.fan_mode == FanMode::Manual {
+                            command::set_fan_mode(device, FanMode::Auto)?;
+                        }
+                        fan.notify(
+                            device,
+                            &format!(
+                                "No fan curve for {:?} mode, the firmware's curve runs",
+                                state.perf_mode
+                            ),
+                        );
+                    }
+                }
+            }
+            let Some(curve) = curve else {
+                return Ok(());
+            };
+            if state.fan_mode != FanMode::Manual {
+                command::set_fan_mode(device, FanMode::Manual)?;
+                current = None;
+            }
+            let rpm = match config.temp_source.read() {
+                Ok(celsius) => {
+                    // the curve's points are in the configured unit
+                    let temperature = unit.convert(celsius);
+                    let rpm = curve.rpm_at(temperature);
+                    debug!("{:.1} {} -> {} RPM", temperature, unit.symbol(), rpm);
+                    rpm
+                }
+                // without a temperature the fan runs as fast as the curve allows
+                Err(e) => {
+                    warn!("{:#}, using the curve's highest RPM", e);
+                    curve.points().iter().map(|p| p.rpm).max().unwrap()
+                }
+            }
+            .clamp(min, max);
+            // compared as the firmware will snap it, so changes within a step aren't sent
+            let rpm = rpm / step * step;
+            if current != Some(rpm) {
+                command::set_fan_rpm(device, FanZone::All, rpm)?;
+                current = Some(rpm);
+            }
+            Ok(())
+        },
+    );
+
+    // other modes already left the fan to the firmware
+    let perf_mode = command::get_perf_state(device)?.perf_mode;
+    match config.curve_for(perf_mode) {
+        Some(_) => {
+            command::set_fan_mode(device, FanMode::Auto)?;
+            fan.notify(device, "Fan curve stopped, fan mode set to Auto");
+        }
+        None => fan.notify(device, "Fan curve stopped"),
+    }
+    result
+}
+
+impl Cli for feature::Fan {
+    fn cmd(&self) -> Option<Command> {
+        Some(
+            clap::Command::new(self.name())
+                .about("Control fan")
+                .subcommand(clap::Command::new("auto").about("Set fan mode to auto"))
+                .subcommand(clap::Command::new("manual").about("Set fan mode to manual"))
+                .subcommand(impl_unary_cmd_cli!{{parse_rpm}, "rpm", "RPM", "Set fan rpm", "Fan RPM within the limits shown by `fan info`, or +N/-N to change the current one"}
+                    .mut_arg("RPM", |arg| arg.allow_negative_numbers(true))
+                    .after_help("Example, the fan mode must be manual first:\n  razer-cli auto fan manual\n  razer-cli auto fan rpm 3500\n  razer-cli auto fan rpm -500"))
+                .subcommand(impl_unary_cmd_cli!{{clap::value_parser!(MaxFanSpeedMode)}, "max", "MAX", "Control Max Fan Speed Mode", "Max Fan Speed Mode"})
+                .subcommand(
+                    clap::Command::new("curve")
+                        .about("Follow a temperature to RPM curve per performance mode until Ctrl-C, then set the fan back to auto")
+                        .arg(arg!(<CURVE> "TOML file with `curve` points, a `temp_source` and an optional `interval` in seconds, or inline points such as 40:2000,60:3000,80:5000"))
+                        .arg(
+                            arg!(--hwmon <PATH> "With inline points: sysfs file to read the temperature from, in millidegrees Celsius")
+                                .value_parser(clap::value_parser!(std::path::PathBuf)),
+                        )
+                        .arg(
+                            arg!(--"temp-command" <COMMAND> "With inline points: shell command printing the temperature in degrees Celsius")
+                                .conflicts_with("hwmon"),
+                        )
+                        .arg(
+                            arg!(--interval <SECONDS> "With inline points: seconds between temperature reads, 2 by default")
+                                .value_parser(clap::value_parser!(u64)),
+                        )
+                        .after_help("Example, inline:\n  razer-cli auto fan curve 40:2000,60:3000,80:5000 --hwmon /sys/class/hwmon/hwmon2/temp1_input\n\nExample curve file:\n  temp_source = { hwmon = \"/sys/class/hwmon/hwmon2/temp1_input\" }\n  # or temp_source = { command = \"sensors -u | awk '/temp1_input/ {print $2; exit}'\" }\n  curve = [{ temperature = 50, rpm = 2000 }, { temperature = 85, rpm = 5000 }]\n  # optional, replaces `curve` in the modes listed\n  [curves]\n  Turbo = [{ temperature = 40, rpm = 3000 }, { temperature = 70, rpm = 5000 }]\n\nPoint temperatures are in °C, or in °F with temperature_unit = \"fahrenheit\" in config.toml. The curve is picked again whenever the performance mode changes. The firmware takes manual RPM in balanced and turbo mode only, so only those can have a curve. In the other modes, and in those without a curve, the firmware's curve runs, e.g. max fan speed with CPU Boost/Overclock and GPU High in custom mode."),
+                )
+                .arg_required_else_help(true),
+        )
+    }
+
+    fn handle(
+        &self,
+        device: &device::Device,
+        matches: &clap::ArgMatches,
+        config: &config::Config,
+    ) -> Result<()> {
+        match matches.subcommand() {
+            Some((ident, matches)) if ident == self.name() => {
+                let fan_mode = || Ok(command::get_perf_state(device)?.fan_mode);
+                if matches.subcommand_matches("auto").is_some() {
+                    command::set_fan_mode(device, FanMode::Auto)?;
+                    verify(matches, FanMode::Auto, fan_mode)?;
+                    self.notify(device, "Fan mode set to Auto");
+                }
+                if matches.subcommand_matches("manual").is_some() {
+                    command::set_fan_mode(device, FanMode::Manual)?;
+                    verify(matches, FanMode::Manual, fan_mode)?;
+                    self.notify(device, "Fan mode set to Manual");
+                }
+                if let Some(rpm_matches) = matches.subcommand_matches("rpm") {
+                    let rpm = match *rpm_matches.get_one::<RpmChange>("RPM").unwrap() {
+                        RpmChange::To(rpm) => rpm,
+                        RpmChange::By(delta) => {
+                            let (min, max) = command::get_fan_rpm_limits(device)?;
+                            let current = command::get_fan_rpm(device, FanZone::Zone1)?[0];
+                            (current as i32 + delta).clamp(min as i32, max as i32) as u16
+                        }
+                    };
+                    command::set_fan_rpm(device, FanZone::All, rpm)?;
+                    // the firmware snaps to its step, readbacks within one step count as set
+                    let step = command::get_fan_rpm_step(device)?;
+                    verify(rpm_matches, vec![rpm; 2], || {
+                        Ok(command::get_fan_rpm(device, FanZone::All)?
+                            .into_iter()
+                            .map(|actual| match actual.abs_diff(rpm) < step {
+                                true => rpm,
+                                false => actual,
+                            })
+                            .collect())
+                    })?;
+                    self.notify(device, &format!("Fan RPM set to {}", rpm));
+                }
+                impl_unary_handle_cli! {<MaxFanSpeedMode>(matches, device, "max", "MAX", command::set_max_fan_speed_mode, command::get_max_fan_speed_mode)}
+                if let Some(curve_matches) = matches.subcommand_matches("curve") {
+                    follow_curve(
+                        self,
+                        device,
+                        &curve_config(curve_matches)?,
+                        config.temperature_unit,
+                    )?;
+                }
+                Ok(())
+            }
+            Some(("info", _)) => {
+                match command::get_perf_state(device) {
+                    Ok(state) => {
+                        // max fan speed only pins the fans in custom mode
+                        let max_speed = state.perf_mode == PerfMode::Custom
+                            && matches!(
+                                command::get_max_fan_speed_mode(device),
+                                Ok(MaxFanSpeedMode::Enable)
+                            );
+                        match (state.fan_mode, max_speed) {
+                            (_, true) => info!(
+                                "Fan: {:?} (firmware-overridden to max speed)",
+                                state.fan_mode
+                            ),
+                            (FanMode::Auto, false) => info!("Fan: {:?}", state.fan_mode),
+                            (FanMode::Manual, false) => {
+                                let rpm = command::get_fan_rpm(device, FanZone::All)?;
+                                info!("Fan: {:?}@{:?} RPM", state.fan_mode, rpm)
+                            }
+                        }
+                    }
+                    Err(e) => error!("{}", e),
+                }
+                let (min, max) = command::get_fan_rpm_limits(device)?;
+                info!(
+                    "Fan RPM limits: {}-{} in steps of {}",
+                    min,
+                    max,
+                    command::get_fan_rpm_step(device)?
+                );
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// Adds `--level N` next to the named boost value, for firmware taking raw levels
+fn with_boost_level(cmd: Command, arg_name: &'static str) -> Command {
+    cmd.arg(
+        arg!(--level <LEVEL> "Raw firmware boost level, on models that take more than the named ones")
+            .value_parser(clap::value_parser!(u8))
+            .conflicts_with(arg_name),
+    )
+    .mut_arg(arg_name, |arg| arg.required_unless_present("level"))
+}
+
+fn boost_level<'a>(
+    matches: &'a clap::ArgMatches,
+    name: &str,
+) -> Option<(&'a clap::ArgMatches, u8)> {
+    let matches = matches.subcommand_matches(name)?;
+    Some((matches, *matches.get_one::<u8>("level")?))
+}
+
+impl Cli for feature::Perf {
+    fn cmd(&self) -> Option<Command> {
+        Some(
+            clap::Command::new(self.name())
+                .about("Control performance modes")
+                .subcommand(impl_unary_cmd_cli!{{clap::value_parser!(PerfMode)}, "mode", "MODE", "Set performance mode", "Performance mode"})
+                .subcommand(with_boost_level(impl_unary_cmd_cli!{{clap::value_parser!(CpuBoost)}, "cpu", "CPU", "Set CPU boost", "CPU boost"}, "CPU"))
+                .subcommand(with_boost_level(impl_unary_cmd_cli!{{clap::value_parser!(GpuBoost)}, "gpu", "GPU", "Set GPU boost", "GPU boost"}, "GPU"))
+                .arg_required_else_help(true),
+        )
+    }
+
+    fn handle(
+        &self,
+        device: &device::Device,
+        matches: &clap::ArgMatches,
+        config: &config::Config,
+    ) -> Result<()> {
+        match matches.subcommand() {
+            Some((ident, matches)) if ident == self.name() => {
+                if let Some(mode_matches) = matches.subcommand_matches("mode") {
+                    let old_mode = command::get_perf_state(device)?.perf_mode;
+                    let new_mode = *mode_matches.get_one::<PerfMode>("MODE").unwrap();
+                    write_setting(
+                        mode_matches,
+                        device,
+                        new_mode,
+                        command::set_perf_mode,
+                        |_| Ok(old_mode),
+                    )?;
+                    verify(mode_matches, new_mode, || {
+                        Ok(command::get_perf_state(device)?.perf_mode)
+                    })?;
+                    self.notify(
+                        device,
+                        &format!(
+                            "Performance mode changed from {:?} to {:?}",
+                            old_mode, new_mode
+                        ),
+                    );
+                }
+                // raw levels count from the named ones, so anything past Overclock is too
+                let overclock = match boost_level(matches, "cpu") {
+                    Some((_, level)) => level >= u8::from(CpuBoost::Overclock),
+                    None => {
+                        matches
+                            .subcommand_matches("cpu")
+                            .and_then(|cpu_matches| cpu_matches.get_one::<CpuBoost>("CPU"))
+                            == Some(&CpuBoost::Overclock)
+                    }
+                };
+                if overclock {
+                    battery_guard(matches, device, config, "CPU overclock")?;
+                }
+                match boost_level(matches, "cpu") {
+                    Some((cpu_matches, level)) => {
+                        command::set_cpu_boost_level(device, level)?;
+                        verify(cpu_matches, level, || command::get_cpu_boost_level(device))?;
+                    }
+                    None => {
+                        impl_unary_handle_cli! {<CpuBoost>(matches, device, "cpu", "CPU", command::set_cpu_boost, command::get_cpu_boost)}
+                    }
+                }
+                match boost_level(matches, "gpu") {
+                    Some((gpu_matches, level)) => {
+                        command::set_gpu_boost_level(device, level)?;
+                        verify(gpu_matches, level, || command::get_gpu_boost_level(device))?;
+                    }
+                    None => {
+                        impl_unary_handle_cli! {<GpuBoost>(matches, device, "gpu", "GPU", command::set_gpu_boost, command::get_gpu_boost)}
+                    }
+                }
+                Ok(())
+            }
+            Some(("info", _)) => {
+                let perf_mode = command::get_perf_state(device).map(|state| state.perf_mode);
+                info!("Performance: {:?}", perf_mode);
+                if let Ok(PerfMode::Custom) = perf_mode {
+                    let cpu_boost = command::get_cpu_boost(device);
+                    let gpu_boost = command::get_gpu_boost(device);
+                    match device.info.boost_levels {
+                        Some(_) => {
+                            info!(
+                                "CPU: {:?} (level {})",
+                                cpu_boost,
+                                command::get_cpu_boost_level(device)?
+                            );
+                            info!(
+                                "GPU: {:?} (level {})",
+                                gpu_boost,
+                                command::get_gpu_boost_level(device)?
+                            );
+                        }
+                        None => {
+                            info!("CPU: {:?}", cpu_boost);
+                            info!("GPU: {:?}", gpu_boost);
+                        }
+                    }
+
+                    if let (Ok(CpuBoost::Boost) | Ok(CpuBoost::Overclock), Ok(GpuBoost::High)) =
+                        (cpu_boost, gpu_boost)
+                    {
+                        info!(
+                            "Max Fan Speed: {:?}",
+                            command::get_max_fan_speed_mode(device)?
+                        );
+                    }
+                }
+                match command::get_active_gpu(device) {
+                    Ok(gpu) => info!("Active GPU: {:?}", gpu),
+                    Err(e) => debug!("Active GPU: {}", e),
+                }
+                let watts = |power: Result<f32>| power.map(|power| format!("{:.1} W", power));
+                let mhz = |clock: Result<u32>| clock.map(|clock| format!("{} MHz", clock));
+                for (label, value) in [
+                    ("CPU power", watts(command::get_cpu_power(device))),
+                    ("CPU clock", mhz(command::get_cpu_clock(device))),
+                    ("GPU power", watts(command::get_gpu_power(device))),
+                    ("GPU clock", mhz(command::get_gpu_clock(device))),
+                ] {
+                    match value {
+                        Ok(value) => info!("{}: {}", label, value),
+                        Err(e) => debug!("{}: {:#}", label, e),
+                    }
+                }
+                match command::get_throttle_state(device) {
+                    Ok(state) => info!(
+                        "CPU throttling: {} ({} core and {} package events since boot)",
+                        match (state.core, state.package) {
+                            (false, false) => "no",
+                            (true, false) => "core",
+                            (false, true) => "package",
+                            (true, true) => "core and package",
+                        },
+                        state.core_events,
+                        state.package_events
+                    ),
+                    Err(e) => debug!("CPU throttling: {:#}", e),
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// Lists what is present even on unsupported hardware, only finding no Razer device fails
+fn enumerate() -> Result<()> {
+    let pids = device::Device::list_pids()?;
+    anyhow::ensure!(
+        !pids.is_empty(),
+        librazer::Error::DeviceNotFound("No Razer devices found".into())
+    );
+    let supported = match device::Device::model() {
+        Ok(model) => {
+            info!("Model: {}", model);
+            descriptor::is_supported(&model)
+        }
+        Err(e) => {
+            info!("Model: unknown ({})", e);
+            false
+        }
+    };
+    info!("Supported: {}", supported);
+    for pid in pids {
+        match librazer::descriptor::classify(pid) {
+            (class, Some(name)) => info!("PID: {:#06x} {:?} ({})", pid, class, name),
+            (class, None) => info!("PID: {:#06x} {:?}", pid, class),
+        }
+    }
+    Ok(())
+}
+
+// Logs one doctor check, returning whether it passed
+fn doctor_check(name: &str, result: Result<String>, fix: &str) -> bool {
+    match result {
+        Ok(detail) => {
+            info!("ok    {:<16} {}", name, detail);
+            true
+        }
+        Err(e) => {
+            warn!("FAIL  {:<16} {:#}", name, e);
+            warn!("      {:<16} {}", "", fix);
+            false
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn find_udev_rule() -> Result<String> {
+    const RULE_DIRS: &[&str] = &[
+        "/etc/udev/rules.d",
+        "/usr/lib/udev/rules.d",
+        "/lib/udev/rules.d",
+    ];
+    RULE_DIRS
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| std::fs::read_to_string(path).is_ok_and(|rules| rules.contains("1532")))
+        .map(|path| path.display().to_string())
+        .ok_or_else(|| anyhow::anyhow!("no rule mentions the Razer vendor id 1532"))
+}
+
+// Walks through the usual setup problems in the order they block each other
+fn doctor() -> Result<()> {
+    let pids = device::Device::list_pids();
+    let mut passed = doctor_check(
+        "HID access",
+        match &pids {
+            Ok(_) => Ok("devices listed".to_string()),
+            Err(e) => Err(anyhow::anyhow!("{:#}", e)),
+        },
+        "hidapi could not list the HID devices",
+    );
+    let pids = pids.unwrap_or_default();
+    passed &= doctor_check(
+        "Razer devices",
+        match pids.is_empty() {
+            true => Err(anyhow::anyhow!("no device with vendor id 0x1532")),
+            false => Ok(pids
+                .iter()
+                .map(|pid| format!("{:#06x}", pid))
+                .collect::<Vec<_>>()
+                .join(", ")),
+        },
+        "Check that this is a Razer laptop and the device is not disabled",
+    );
+
+    let model = device::read_device_model();
+    let descriptor = model
+        .as_ref()
+        .ok()
+        .and_then(|model| descriptor::match_model(model));
+    passed &= doctor_check(
+        "Model",
+        match &model {
+            Ok(model) => Ok(model.clone()),
+            Err(e) => Err(anyhow::anyhow!("{:#}", e)),
+        },
+        "Set pid in config.toml or use `manual --pid <PID>`",
+    );
+    passed &= doctor_check(
+        "Descriptor",
+        match (&model, descriptor) {
+            (_, Some(d)) => Ok(d.name.to_string()),
+            (Ok(model), None) => Err(anyhow::anyhow!("{} is not in the supported list", model)),
+            (Err(_), None) => Err(anyhow::anyhow!("the model is unknown")),
+        },
+        "Try `auto --pid <PID>` or `manual --pid <PID>`, and report the model and PIDs",
+    );
+
+    #[cfg(target_os = "linux")]
+    {
+        passed &= doctor_check(
+            "udev rules",
+            find_udev_rule(),
+            "Install the udev rules from the README",
+        );
+    }
+
+    let target = descriptor.cloned().or_else(|| {
+        pids.iter()
+            .find(|&&pid| librazer::descriptor::classify(pid).0 == DeviceClass::Laptop)
+            .map(|&pid| manual_descriptor(pid))
+    });
+    if let Some(target) = target {
+        let pid = target.pid;
+        let opened = device::Device::new(target);
+        let fix = match opened
+            .as_ref()
+            .err()
+            .and_then(|e| e.downcast_ref::<librazer::Error>())
+        {
+            Some(librazer::Error::PermissionDenied(_)) => {
+                "Check the udev rules and that your user may access the device"
+            }
+            _ => "Close Razer Synapse or other programs using the device",
+        };
+        passed &= doctor_check("Open device", opened.map(|_| format!("{:#06x}", pid)), fix);
+    }
+
+    anyhow::ensure!(passed, "Some checks failed, see the suggested fixes above");
+    Ok(())
+}
+
+// Reads every feature the descriptor claims, so a stale descriptor shows up before a
+// feature is relied on
+fn self_check(device: &device::Device) {
+    let mut failed = 0;
+    info!("{:<18} Status", "Feature");
+    for name in device.info.features {
+        match feature::find(name) {
+            Ok(info) => match info.get(device) {
+                Ok(_) => info!("{:<18} ok", name),
+                Err(e) => {
+                    failed += 1;
+                    warn!("{:<18} no response: {}", name, e);
+                }
+            },
+            Err(_) => info!("{:<18} not readable, skipped", name),
+        }
+    }
+    if failed > 0 {
+        warn!(
+            "{} of {} features did not respond, the descriptor for {} may be out of date",
+            failed,
+            device.info.features.len(),
+            device.info.name
+        );
+    }
+}
+
+// Reads every known feature whether or not the descriptor declares it, to find out which
+// opcodes work on a model that is being brought up
+fn probe_all(device: &device::Device) {
+    info!("{:<18} {:<10} Result", "Feature", "Declared");
+    for name in feature::ALL_FEATURES {
+        let declared = match device.info.features.contains(name) {
+            true => "yes",
+            false => "no, probed",
+        };
+        match feature::find(name).map(|info| info.get(device)) {
+            Ok(Ok(value)) => info!("{:<18} {:<10} {}", name, declared, raw_value(&value)),
+            Ok(Err(e)) => info!("{:<18} {:<10} error: {}", name, declared, e),
+            Err(_) => info!("{:<18} {:<10} not readable, skipped", name, declared),
+        }
+    }
+}
+
+// Setters need no value with --query, it reads the feature instead
+fn allow_query(cmd: Command) -> Command {
+    let cmd = cmd.mut_args(|arg| match arg.is_required_set() {
+        true => arg.required(false).required_unless_present("query"),
+        false => arg,
+    });
+    let subcommands: Vec<String> = cmd
+        .get_subcommands()
+        .map(|s| s.get_name().to_string())
+        .collect();
+    subcommands
+        .iter()
+        .fold(cmd, |cmd, name| cmd.mut_subcommand(name, allow_query))
+}
+
+// Enum values match regardless of case, e.g. `perf mode Custom` and `perf mode custom`
+fn ignore_case(cmd: Command) -> Command {
+    let cmd = cmd.mut_args(|arg| match arg.get_possible_values().is_empty() {
+        true => arg,
+        false => arg.ignore_case(true),
+    });
+    let subcommands: Vec<String> = cmd
+        .get_subcommands()
+        .map(|s| s.get_name().to_string())
+        .collect();
+    subcommands
+        .iter()
+        .fold(cmd, |cmd, name| cmd.mut_subcommand(name, ignore_case))
+}
+
+// Commands without their own examples whose value is an enum get one with its first value,
+// clap already lists all of them next to the argument
+fn with_examples(cmd: Command, path: &str) -> Command {
+    let path = format!("{} {}", path, cmd.get_name());
+    let value = cmd
+        .get_positionals()
+        .next()
+        .and_then(|arg| arg.get_possible_values().first().cloned());
+    let cmd = match (cmd.get_after_help(), value) {
+        (None, Some(value)) => cmd.after_help(format!(
+            "Example:\n  razer-cli {} {}",
+            path,
+            value.get_name()
+        )),
+        _ => cmd,
+    };
+    let subcommands: Vec<String> = cmd
+        .get_subcommands()
+        .map(|s| s.get_name().to_string())
+        .collect();
+    subcommands.iter().fold(cmd, |cmd, name| {
+        cmd.mut_subcommand(name, |sub| with_examples(sub, &path))
+    })
+}
+
+// Commands kept with --read-only besides the feature queries, they only read the device
+const READ_ONLY_COMMANDS: &[&str] = &["benchmark", "save-boot-profile"];
+
+// A feature command with --read-only: prints the value, or the field named by a subcommand
+fn query_cmd(info: &feature::FeatureInfo) -> Command {
+    let cmd = clap::Command::new(info.name).about(info.description);
+    match info.value_type {
+        feature::ValueType::Object(fields) => fields.iter().fold(cmd, |cmd, (field, _)| {
+            cmd.subcommand(clap::Command::new(*field).about(format!("Print the {}", field)))
+        }),
+        _ => cmd,
+    }
+}
+
+fn read_only_cmd(cmd: Command, features: &[Box<dyn Cli>]) -> Command {
+    features
+        .iter()
+        .fold(cmd, |cmd, f| match feature::find(f.name()) {
+            Ok(info) => cmd.subcommand(query_cmd(info)),
+            Err(_) if READ_ONLY_COMMANDS.contains(&f.name()) => match f.cmd() {
+                Some(sub) => cmd.subcommand(sub),
+                None => cmd,
+            },
+            Err(_) => cmd,
+        })
+}
+
+fn update_cmd(cmd: Command, features: &[Box<dyn Cli>]) -> Command {
+    features.iter().filter_map(|f| f.cmd()).fold(cmd, |cmd, f| {
+        cmd.subcommand(with_examples(ignore_case(allow_query(f)), "auto"))
+    })
+}
+
+// Plain text for status bars: strings unquoted, objects as key=value pairs
+fn raw_value(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Object(map) => map
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, raw_value(value)))
+            .collect::<Vec<_>>()
+            .join(" "),
+        value => value.to_string(),
+    }
+}
+
+// Prints the value of the feature named by the subcommand, or one field of it, e.g. `perf mode`
+fn query(device: &device::Device, matches: &clap::ArgMatches) -> Result<()> {
+    let Some((name, matches)) = matches.subcommand().filter(|(name, _)| *name != "info") else {
+        anyhow::bail!("--query needs a feature, e.g. `auto perf mode --query`");
+    };
+    let info = feature::find(name).map_err(|_| anyhow::anyhow!("{} can't be queried", name))?;
+    let value = info.get(device)?;
+    let value = match (matches.subcommand(), info.value_type) {
+        (None, _) => value,
+        (Some((field, _)), feature::ValueType::Object(fields))
+            if fields.iter().any(|(f, _)| *f == field) =>
+        {
+            value.get(field).cloned().ok_or_else(|| {
+                anyhow::anyhow!("{} {} is not available in the current mode", name, field)
+            })?
+        }
+        (Some((field, _)), _) => anyhow::bail!("{} {} can't be queried", name, field),
+    };
+    println!("{}", raw_value(&value));
+    Ok(())
+}
+
+// Prints `contents`, or writes them to the `--output` file
+fn write_output(matches: &clap::ArgMatches, contents: &str) -> Result<()> {
+    match matches.get_one::<std::path::PathBuf>("output") {
+        Some(path) => config::write_atomic(path, contents),
+        None => {
+            println!("{}", contents);
+            Ok(())
+        }
+    }
+}
+
+// Features a command may change, read before and after it for the history
+fn tracked_features(device: &device::Device, matches: &clap::ArgMatches) -> Vec<&'static str> {
+    let readable = || {
+        device
+            .info
+            .features
+            .iter()
+            .copied()
+            .filter(|name| feature::find(name).is_ok())
+    };
+    match matches.subcommand() {
+        Some(("apply" | "apply-boot-profile", _)) => readable().collect(),
+        Some((name, _)) => readable().filter(|f| *f == name).collect(),
+        None => Vec::new(),
+    }
+}
+
+fn handle(
+    device: &device::Device,
+    matches: &clap::ArgMatches,
+    features: &Vec<Box<dyn Cli>>,
+    config: &config::Config,
+) -> Result<()> {
+    // with --read-only every feature command is a query
+    let read_only_query = matches.get_flag("read-only")
+        && matches
+            .subcommand()
+            .is_some_and(|(name, _)| feature::find(name).is_ok());
+    if matches.get_flag("query") || read_only_query {
+        return query(device, matches);
+    }
+    let format = matches.get_one::<String>("format").unwrap();
+    if let (Some(("info", _)), "json" | "table") = (matches.subcommand(), format.as_str()) {
+        use std::io::IsTerminal;
+        let terminal = std::io::stdout().is_terminal();
+        match format.as_str() {
+            "json" => write_output(matches, &report::json(device)?)?,
+            _ => report::print_table(
+                device,
+                terminal && EarlyArgs::scan().color_choice() != clap::ColorChoice::Never,
+                terminal
+                    .then(|| ratatui::crossterm::terminal::size().ok())
+                    .flatten()
+                    .map(|(columns, _)| columns as usize),
+            ),
+        }
+        return Ok(());
+    }
+    if let Some(("info", info_matches)) = matches.subcommand() {
+        info!("Device: {:?}", device.info);
+        match command::get_battery_health(device) {
+            Ok(health) => info!(
+                "Battery health: {} of {} mWh, {:.1}% wear, {} cycles",
+                health.full_mwh,
+                health.design_mwh,
+                health.wear_pct(),
+                health
+                    .cycle_count
+                    .map_or_else(|| "unknown".to_string(), |count| count.to_string())
+            ),
+            Err(e) => debug!("Battery health: {}", e),
+        }
+        if info_matches.get_flag("probe-all") {
+            probe_all(device);
+            return Ok(());
+        }
+    }
+    if matches.get_flag("self-check") {
+        self_check(device);
+    }
+
+    let started = std::time::Instant::now();
+    if let Some(("info", _)) = matches.subcommand() {
+        // getters legitimately fail on partially supported models, show what can be read
+        let failed: Vec<&str> = features
+            .iter()
+            .filter(|f| match f.handle(device, matches, config) {
+                Ok(()) => false,
+                Err(e) => {
+                    error!("{}: {:#}", f.name(), e);
+                    true
+                }
+            })
+            .map(|f| f.name())
+            .collect();
+        if !failed.is_empty() {
+            warn!("Failed to read {}", failed.join(", "));
+        }
+    } else {
+        let tracked = match config.history {
+            true => tracked_features(device, matches),
+            false => Vec::new(),
+        };
+        let before = history::snapshot(device, &tracked);
+        for f in features {
+            f.handle(device, matches, config)?;
+        }
+        if !tracked.is_empty() {
+            history::record(device, &before, &history::snapshot(device, &tracked))?;
+        }
+    }
+    debug!("Handled in {:?}", started.elapsed());
+    Ok(())
+}
+
+// With --every, handles the command again each interval until interrupted. Only the first
+// run may fail, after a later failure the device is reopened since it may have gone away
+// during sleep.
+fn handle_every(
+    mut device: device::Device,
+    matches: &clap::ArgMatches,
+    submatches: &clap::ArgMatches,
+    features: &Vec<Box<dyn Cli>>,
+    config: &config::Config,
+) -> Result<()> {
+    let Some(&interval) = matches.get_one::<u64>("every") else {
+        return handle(&device, submatches, features, config);
+    };
+    let mut first = true;
+    let mut stale = false;
+    run_until_interrupted(std::time::Duration::from_secs(interval), || {
+        if stale {
+            match device.reopen() {
+                Ok(reopened) => device = reopened,
+                Err(e) => {
+                    warn!("Failed to reopen the device: {:#}", e);
+                    return Ok(());
+                }
+            }
+        }
+        stale = match handle(&device, submatches, features, config) {
+            Ok(()) => false,
+            Err(e) if first => return Err(e),
+            Err(e) => {
+                warn!("{:#}, reopening the device", e);
+                true
+            }
+        };
+        first = false;
+        Ok(())
+    })
+}
+
+// Features requested with `manual --features`, scanned before clap runs because the
+// command tree has to be narrowed before it is built. Clap validates the names later.
+fn requested_manual_features() -> Option<Vec<&'static str>> {
+    let mut args = std::env::args_os()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .skip_while(|arg| arg != "manual");
+    while let Some(arg) = args.next() {
+        let value = match arg.strip_prefix("--features=") {
+            Some(value) => value.to_string(),
+            None if arg == "-f" || arg == "--features" => args.next()?,
+            None => continue,
+        };
+        let requested: Vec<&str> = value.split(',').map(str::trim).collect();
+        return Some(
+            feature::ALL_FEATURES
+                .iter()
+                .copied()
+                .filter(|f| requested.contains(f))
+                .collect(),
+        );
+    }
+    None
+}
+
+fn gen_cli_features(feature_list: &[&str]) -> Vec<Box<dyn Cli>> {
+    use feature::*;
+    librazer::iter_features!(|_, feature| -> Box<dyn Cli> { Box::new(feature) })
+        .into_iter()
+        .filter(|f| feature_list.contains(&f.name()))
+        .collect()
+}
+
+// Exit codes are documented in the README
+fn exit_code(error: &anyhow::Error) -> u8 {
+    match error
+        .chain()
+        .find_map(|e| e.downcast_ref::<librazer::Error>())
+    {
+        Some(librazer::Error::DeviceNotFound(_)) => 2,
+        Some(librazer::Error::PermissionDenied(_)) => 3,
+        Some(librazer::Error::Unsupported(_)) => 4,
+        Some(librazer::Error::Protocol(_)) => 5,
+        Some(librazer::Error::VerificationFailed { .. }) => 6,
+        Some(librazer::Error::Timeout(_)) => 7,
+        None => 1,
+    }
+}
+
+fn main() -> std::process::ExitCode {
+    match run() {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            std::process::ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+// Options needed before the command tree is built, scanned from the raw arguments.
+// They are also declared as global clap args so they are documented and accepted anywhere.
+struct EarlyArgs {
+    no_color: bool,
+    quiet: bool,
+    verbosity: usize,
+    log_file: Option<std::path::PathBuf>,
+    // first positional argument, i.e. auto, manual or enumerate
+    subcommand: Option<String>,
+    // its position in the arguments after the program name
+    subcommand_index: Option<usize>,
+    // `auto --pid`, clap validates it again later
+    auto_pid: Option<u16>,
+    // `auto --model-override`, checked against the supported models when applied
+    model_override: Option<String>,
+    // --strict, detection happens before clap runs
+    strict: bool,
+    // --read-only, the command tree depends on it
+    read_only: bool,
+}
+
+impl EarlyArgs {
+    // global options taking a value, needed to tell the value apart from the subcommand
+    const VALUE_OPTIONS: &'static [&'static str] = &[
+        "--log-file",
+        "--timeout",
+        "--retries",
+        "--format",
+        "--wait-ready",
+        "--every",
+        "--output",
+        "--pre-write-delay",
+        "--post-write-delay",
+    ];
+
+    fn value_of(args: &[String], option: &str) -> Option<String> {
+        args.iter().enumerate().find_map(|(i, arg)| {
+            match arg.strip_prefix(option)?.strip_prefix('=') {
+                Some(value) => Some(value.to_string()),
+                None if arg == option => args.get(i + 1).cloned(),
+                None => None,
+            }
+        })
+    }
+
+    // 1 for --verbose, N for -v repeated N times, 0 for any other argument
+    fn verbosity(arg: &str) -> usize {
+        match arg.strip_prefix('-') {
+            Some("-verbose") => 1,
+            Some(flags) if !flags.is_empty() && flags.chars().all(|c| c == 'v') => flags.len(),
+            _ => 0,
+        }
+    }
+
+    fn scan() -> Self {
+        let args: Vec<String> = std::env::args_os()
+            .skip(1)
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        let mut positionals = args.iter().enumerate().filter(|&(i, arg)| {
+            let follows_value_option = i > 0 && Self::VALUE_OPTIONS.contains(&args[i - 1].as_str());
+            !arg.starts_with('-') && !follows_value_option
+        });
+        let verbosity = args.iter().map(|arg| Self::verbosity(arg)).sum();
+        let auto_args: Vec<String> = args
+            .iter()
+            .skip_while(|arg| *arg != "auto")
+            .cloned()
+            .collect();
+        let auto_pid = Self::value_of(&auto_args, "--pid")
+            .or_else(|| Self::value_of(&auto_args, "-p"))
+            .and_then(|pid| clap_num::maybe_hex::<u16>(&pid).ok());
+        let subcommand = positionals.next();
+        Self {
+            no_color: args.iter().any(|arg| arg == "--no-color"),
+            quiet: args.iter().any(|arg| arg == "-q" || arg == "--quiet"),
+            verbosity,
+            log_file: Self::value_of(&args, "--log-file").map(Into::into),
+            subcommand: subcommand.map(|(_, arg)| arg.clone()),
+            subcommand_index: subcommand.map(|(i, _)| i),
+            auto_pid,
+            model_override: Self::value_of(&auto_args, "--model-override"),
+            strict: args.iter().any(|arg| arg == "--strict"),
+            read_only: args.iter().any(|arg| arg == "--read-only"),
+        }
+    }
+
+    // Honors https://no-color.org and --no-color, otherwise colors only when attached to a terminal
+    fn color_choice(&self) -> clap::ColorChoice {
+        let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        if no_color_env || self.no_color {
+            clap::ColorChoice::Never
+        } else {
+            clap::ColorChoice::Auto
+        }
+    }
+
+    // `default` applies when neither -q nor -v is given
+    fn log_level(&self, default: log::LevelFilter) -> log::LevelFilter {
+        match (self.quiet, self.verbosity) {
+            (true, _) => log::LevelFilter::Warn,
+            (false, 0) => default,
+            (false, 1) => log::LevelFilter::Debug,
+            _ => log::LevelFilter::Trace,
+        }
+    }
+
+    // --quiet only silences the console, the log file keeps the full record
+    fn log_file_level(&self, default: log::LevelFilter) -> log::LevelFilter {
+        match self.verbosity {
+            0 => default,
+            1 => log::LevelFilter::Debug,
+            _ => log::LevelFilter::Trace,
+        }
+    }
+}
+
+fn manual_descriptor(pid: u16) -> librazer::descriptor::Descriptor {
+    librazer::descriptor::Descriptor {
+        model_number_prefix: "Unknown",
+        name: "Unknown",
+        pid,
+        features: feature::ALL_FEATURES,
+        key_matrix: Some((6, 16)),
+        perf_modes: &[
+            PerfMode::Balanced,
+            PerfMode::Turbo,
+            PerfMode::Creator,
+            PerfMode::Silent,
+            PerfMode::Custom,
+        ],
+        boost_levels: Some((u8::MAX, u8::MAX)),
+        fan_rpm_limits: (2000, 5000),
+    }
+}
+
+// `manual --features` as a descriptor feature list, in the order of ALL_FEATURES. Leaked
+// once per run: descriptors hold static lists.
+fn static_features<'a>(requested: impl IntoIterator<Item = &'a String>) -> &'static [&'static str] {
+    let requested: Vec<&String> = requested.into_iter().collect();
+    feature::ALL_FEATURES
+        .iter()
+        .copied()
+        .filter(|f| requested.iter().any(|r| r == f))
+        .collect::<Vec<_>>()
+        .leak()
+}
+
+fn wait_ready(device: &device::Device, matches: &clap::ArgMatches) -> Result<()> {
+    match matches.get_one::<u64>("wait-ready") {
+        Some(&ms) => device.wait_ready(std::time::Duration::from_millis(ms)),
+        None => Ok(()),
+    }
+}
+
+// Fails with the PIDs that are present, which says more than a failed open
+fn ensure_pid_present(pid: u16) -> Result<()> {
+    let pids = device::Device::list_pids()?;
+    if pids.contains(&pid) {
+        return Ok(());
+    }
+    let present = match pids.is_empty() {
+        true => "no Razer devices are present".to_string(),
+        false => format!(
+            "present Razer PIDs: {}",
+            pids.iter()
+                .map(|pid| format!("{:#06x}", pid))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    anyhow::bail!(librazer::Error::DeviceNotFound(format!(
+        "PID {:#06x} is not connected, {}",
+        pid, present
+    )))
+}
+
+// Descriptor of the detected model talking to `pid`, for when the model is known but the
+// PID differs from the descriptor's, e.g. after a firmware update
+fn descriptor_with_pid(pid: u16) -> librazer::descriptor::Descriptor {
+    let detected = device::Device::enumerate()
+        .ok()
+        .and_then(|(_, model)| descriptor::match_model(&model));
+    match detected.or_else(|| librazer::descriptor::for_pid(pid)) {
+        Some(descriptor) => {
+            debug!(
+                "Using the {} descriptor with PID {:#06x}",
+                descriptor.name, pid
+            );
+            librazer::descriptor::Descriptor {
+                pid,
+                ..descriptor.clone()
+            }
+        }
+        None => {
+            warn!(
+                "No descriptor matches the model or PID {:#06x}, enabling all features",
+                pid
+            );
+            manual_descriptor(pid)
+        }
+    }
+}
+
+// `auto --model-override`: the descriptor of another supported model, e.g. when the
+// detected one lists too few features for this revision, on the PID auto mode picked
+fn override_model(mut device: device::Device, model: &str) -> Result<device::Device> {
+    let Some(descriptor) = descriptor::match_model(model) else {
+        anyhow::bail!(librazer::Error::Unsupported(format!(
+            "Model {} is not supported, known models are {}",
+            model,
+            descriptor::SUPPORTED
+                .iter()
+                .map(|d| d.model_number_prefix)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )));
+    };
+    info!(
+        "Using the {} descriptor with PID {:#06x}",
+        descriptor.name, device.info.pid
+    );
+    device.info = librazer::descriptor::Descriptor {
+        pid: device.info.pid,
+        ..descriptor.clone()
+    };
+    Ok(device)
+}
+
+// On detection failure, lists the present Razer PIDs with the manual command for each,
+// and offers to continue in manual mode when there is exactly one and we have a terminal
+fn detect_or_suggest_manual(strict: bool) -> Result<device::Device> {
+    let detected = match strict {
+        true => device::Device::detect_strict(),
+        false => device::Device::detect(),
+    };
+    let error = match detected {
+        Ok(device) => return Ok(device),
+        Err(error) => error,
+    };
+    let pids = device::Device::list_pids().unwrap_or_default();
+    if pids.is_empty() {
+        return Err(error);
+    }
+
+    error!("{}", error);
+    info!("Razer devices present, try manual mode:");
+    for pid in &pids {
+        info!("  razer-cli manual --pid {:#06x} info", pid);
+    }
+
+    if let [pid] = pids[..] {
+        if confirm(&format!("Continue in manual mode with PID {:#06x}?", pid))? {
+            return device::Device::new(manual_descriptor(pid));
+        }
+    }
+    Err(error)
+}
+
+// Asks a yes/no question, answering no when there is no terminal to ask on
+fn confirm(question: &str) -> Result<bool> {
+    use std::io::IsTerminal;
+    if !(std::io::stdin().is_terminal() && std::io::stderr().is_terminal()) {
+        return Ok(false);
+    }
+    eprint!("{} [y/N] ", question);
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().eq_ignore_ascii_case("y"))
+}
+
+// Every Razer laptop has a few of the features, fewer most likely means a broken descriptor
+const MIN_EXPECTED_FEATURES: usize = 2;
+
+fn warn_few_features(descriptor: &librazer::descriptor::Descriptor) {
+    let enabled = feature::ALL_FEATURES
+        .iter()
+        .filter(|f| descriptor.features.contains(f))
+        .count();
+    if enabled >= MIN_EXPECTED_FEATURES {
+        return;
+    }
+    let excluded: Vec<&str> = feature::ALL_FEATURES
+        .iter()
+        .copied()
+        .filter(|f| !descriptor.features.contains(f))
+        .collect();
+    warn!(
+        "The descriptor for {} ({}) enables only {} feature(s), leaving out {}. Check its feature list, or use `manual` to try them",
+        descriptor.name,
+        descriptor.model_number_prefix,
+        enabled,
+        excluded.join(", ")
+    );
+}
+
+// Commands available on every device besides the descriptor's features
+fn extra_cli_features() -> Vec<Box<dyn Cli>> {
+    let mut features: Vec<Box<dyn Cli>> = feature::registered()
+        .into_iter()
+        .map(|info| Box::new(RegisteredFeature(info)) as Box<dyn Cli>)
+        .collect();
+    features.push(Box::new(CustomCommand));
+    features.push(Box::new(Dashboard));
+    features.push(Box::new(Benchmark));
+    features.push(Box::new(Serve));
+    features.push(Box::new(Profile));
+    features.push(Box::new(SaveBootProfile));
+    features.push(Box::new(ApplyBootProfile));
+    features
+}
+
+fn run() -> Result<()> {
+    let early_args = EarlyArgs::scan();
+    let color = early_args.color_choice();
+    let config = config::Config::load()?;
+    let default_level = config.log_level()?.unwrap_or(log::LevelFilter::Info);
+
+    // Initialize logging FIRST
+    logging::init(
+        early_args.log_level(default_level),
+        early_args.log_file_level(default_level),
+        match color {
+            clap::ColorChoice::Never => env_logger::WriteStyle::Never,
+            _ => env_logger::WriteStyle::Auto,
+        },
+        early_args.log_file.as_deref(),
+    )?;
+
+    let info_cmd = clap::Command::new("info")
+        .about("Get device info")
+        .arg(arg!(
+            --"probe-all" "Read every known feature, including those the descriptor doesn't declare"
+        ));
+    let auto_cmd = clap::Command::new("auto")
+        .about("Automatically detect supported Razer device and enable device specific features")
+        .arg(
+            arg!(-p --pid <PID> "Talk to this PID, keeping the features of the detected model")
+                .value_parser(clap_num::maybe_hex::<u16>),
+        )
+        .arg(arg!(--"model-override" <MODEL> "Use the features of this supported model number, e.g. RZ09-0509, keeping the detected PID"))
+        .subcommand(info_cmd.clone())
+        .subcommand_required(true);
+
+    let manual_cmd =clap::Command::new("manual").about("Manually specify PID of the Razer device and enable all features (many might not work)")
+            .arg(
                 arg!(-p --pid <PID> "PID of the Razer device to use")
                 .required(true)
                 .value_parser(clap_num::maybe_hex::<u16>)
             )
+            .arg(
+                arg!(-f --features <FEATURES> "Comma separated list of features to enable, e.g. fan,perf")
+                .value_delimiter(',')
+                .value_parser(clap::builder::PossibleValuesParser::new(feature::ALL_FEATURES))
+            )
             .arg_required_else_help(true)
             .subcommand(info_cmd)
             .subcommand_required(true);
 
+    // `razer-cli perf mode silent` is short for `razer-cli auto perf mode silent`
+    let mut args: Vec<std::ffi::OsString> = std::env::args_os().collect();
+    let implicit_auto = early_args.subcommand.as_deref().is_some_and(|name| {
+        extra_cli_features()
+            .iter()
+            .map(|f| f.name())
+            .chain(feature::ALL_FEATURES.iter().copied())
+            .chain(["info"])
+            .any(|f| f == name)
+    });
+    if implicit_auto {
+        args.insert(early_args.subcommand_index.unwrap() + 1, "auto".into());
+    }
+
     // TODO: find a better way to detect auto mode in advance
-    let is_auto_mode = std::env::args_os().nth(1) == Some("auto".into());
+    let is_auto_mode = implicit_auto || early_args.subcommand.as_deref() == Some("auto");
     let device = match is_auto_mode {
-        true => Some(device::Device::detect()?),
+        true => Some(match (early_args.auto_pid, config.pid) {
+            (Some(pid), _) => device::Device::new(descriptor_with_pid(pid))?,
+            (None, Some(pid)) => {
+                debug!("Opening PID {:#06x} from the config file", pid);
+                device::Device::new(
+                    librazer::descriptor::for_pid(pid)
+                        .cloned()
+                        .unwrap_or_else(|| manual_descriptor(pid)),
+                )?
+            }
+            (None, None) => detect_or_suggest_manual(early_args.strict)?,
+        })
+        .map(|device| match &early_args.model_override {
+            Some(model) => override_model(device, model),
+            None => Ok(device),
+        })
+        .transpose()?,
         _ => None,
     };
-    let feature_list = match device {
-        Some(ref device) => device.info.features,
+    let manual_features = requested_manual_features();
+    let feature_list = match (&device, &manual_features) {
+        (Some(device), _) => device.info.features,
+        (None, Some(features)) => features.as_slice(),
         _ => feature::ALL_FEATURES,
     };
 
+    if let Some(device) = &device {
+        warn_few_features(&device.info);
+    }
+
     let mut cli_features: Vec<Box<dyn Cli>> = gen_cli_features(feature_list);
-    cli_features.push(Box::new(CustomCommand));
+    cli_features.extend(extra_cli_features());
 
     let cmd = clap::command!()
-        .color(clap::ColorChoice::Always)
+        .color(color)
+        .arg(arg!(--"no-color" "Disable colored output").global(true))
+        .arg(
+            arg!(-q --quiet "Only print warnings and errors")
+                .global(true)
+                .conflicts_with("verbose"),
+        )
+        .arg(arg!(-v --verbose... "Print debug output, repeat for trace output").global(true))
+        .arg(
+            arg!(--"log-file" <PATH> "Also write logs to a file, rotated once it reaches 10 MiB")
+                .global(true)
+                .value_parser(clap::value_parser!(std::path::PathBuf)),
+        )
+        .arg(
+            arg!(--verify "Read settings back after changing them and fail if they differ")
+                .global(true),
+        )
+        .arg(
+            arg!(--timeout <MS> "Fail a device command that gets no answer within this many milliseconds")
+                .global(true)
+                .default_value("500")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .arg(
+            arg!(--retries <N> "Send a device command that timed out or got a malformed response again, up to N times")
+                .global(true)
+                .value_parser(clap::value_parser!(u32)),
+        )
+        .arg(
+            arg!(--format <FORMAT> "Output of `info`: log lines, JSON or an aligned table of every feature. `apply` prints a JSON summary of what changed with json")
+                .global(true)
+                .default_value("log")
+                .value_parser(["log", "json", "table"]),
+        )
+        .arg(
+            arg!(--"wait-ready" <MS> "Wait up to this many milliseconds for the device to answer before sending commands, e.g. after a hotplug")
+                .global(true)
+                .value_parser(clap::value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"no-delays" "Send and read without pausing, for benchmarking the HID transport")
+                .global(true)
+                .conflicts_with("adaptive-timing"),
+        )
+        .arg(
+            arg!(--"pre-write-delay" <US> "Microseconds to pause before writing each command, see `auto benchmark`")
+                .global(true)
+                .value_parser(clap::value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"post-write-delay" <US> "Microseconds to wait after writing before reading the answer, see `auto benchmark`")
+                .global(true)
+                .value_parser(clap::value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"adaptive-timing" "Tune the wait for device responses while running, for daemons sending many commands")
+                .global(true),
+        )
+        .arg(
+            arg!(--query "Print a feature's current value to stdout instead of changing it, e.g. `auto perf mode --query`")
+                .global(true),
+        )
+        .arg(
+            arg!(--notify "Also show changes as desktop notifications (Windows toasts)")
+                .global(true),
+        )
+        .arg(
+            arg!(--"self-check" "Read every feature of the device first and warn about those not responding")
+                .global(true),
+        )
+        .arg(
+            arg!(--"skip-unchanged" "Read a setting first and only send it when it differs, e.g. with --every")
+                .global(true),
+        )
+        .arg(
+            arg!(--"battery-guard" "Refuse CPU overclock while on battery, where it can brown out some models")
+                .global(true),
+        )
+        .arg(
+            arg!(--"read-only" "Only offer info and reading features, and refuse any command that changes a setting, e.g. for monitoring")
+                .global(true),
+        )
+        .arg(
+            arg!(--strict "Fail instead of warning when the detected model's PID is not present")
+                .global(true),
+        )
+        .arg(
+            arg!(--every <SECONDS> "Repeat the command every this many seconds until Ctrl-C, for firmware that forgets settings")
+                .global(true)
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .arg(
+            arg!(--output <PATH> "Write JSON output (`--format json`, `profile-schema`, `save-boot-profile`) to this file instead, replacing it atomically")
+                .global(true)
+                .value_parser(clap::value_parser!(std::path::PathBuf)),
+        )
         .subcommand_required(true)
-        .subcommand(update_cmd(auto_cmd, &cli_features))
-        .subcommand(update_cmd(manual_cmd, &cli_features))
-        .subcommand(clap::Command::new("enumerate").about("List discovered Razer devices"));
+        .after_help("Feature commands may leave out `auto`, e.g. `razer-cli perf mode silent`")
+        .subcommand(match early_args.read_only {
+            true => read_only_cmd(auto_cmd, &cli_features),
+            false => update_cmd(auto_cmd, &cli_features),
+        })
+        .subcommand(match early_args.read_only {
+            true => read_only_cmd(manual_cmd, &cli_features),
+            false => update_cmd(manual_cmd, &cli_features),
+        })
+        .subcommand(clap::Command::new("enumerate").about("List discovered Razer devices"))
+        .subcommand(
+            clap::Command::new("doctor")
+                .about("Check the setup step by step and suggest fixes for what fails"),
+        )
+        .subcommand(
+            clap::Command::new("history")
+                .about("Print the last settings changed, with `history = true` in config.toml")
+                .arg(
+                    arg!(-n --count <N> "Number of changes")
+                        .default_value("20")
+                        .value_parser(clap::value_parser!(usize)),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("profile-schema")
+                .about("Print the JSON Schema of `apply` profiles, e.g. for editor validation"),
+        );
 
-    let matches = cmd.get_matches();
+    let matches = cmd.get_matches_from(args);
+    if matches.get_flag("notify") {
+        notify::enable_desktop();
+    }
+    let defaults = device::DeviceConfig::default();
+    let micros = |name: &str, default| {
+        matches
+            .get_one::<u64>(name)
+            .map_or(default, |&us| std::time::Duration::from_micros(us))
+    };
+    let device_config = device::DeviceConfig {
+        timeout: std::time::Duration::from_millis(*matches.get_one::<u64>("timeout").unwrap()),
+        adaptive_timing: matches.get_flag("adaptive-timing"),
+        no_delays: matches.get_flag("no-delays"),
+        pre_write_delay: micros("pre-write-delay", defaults.pre_write_delay),
+        post_write_delay: micros("post-write-delay", defaults.post_write_delay),
+        read_only: matches.get_flag("read-only"),
+        retries: matches
+            .get_one::<u32>("retries")
+            .copied()
+            .or(config.retries)
+            .unwrap_or(defaults.retries),
+    };
 
     match matches.subcommand() {
         Some(("enumerate", _)) => {
             enumerate()?;
         }
+        Some(("doctor", _)) => {
+            doctor()?;
+        }
+        Some(("history", history_matches)) => {
+            history::print(*history_matches.get_one::<usize>("count").unwrap())?;
+        }
+        Some(("profile-schema", _)) => {
+            // RPM range of the detected model, of all supported models without one
+            let schema = match device::Device::enumerate()
+                .ok()
+                .and_then(|(_, model)| descriptor::match_model(&model))
+            {
+                Some(descriptor) => feature::profile_schema_for(descriptor),
+                None => feature::profile_schema(),
+            };
+            write_output(&matches, &serde_json::to_string_pretty(&schema)?)?;
+        }
         Some(("auto", submatches)) => {
-            handle(&device.unwrap(), submatches, &cli_features)?;
+            let mut device = device.unwrap();
+            device.set_config(device_config);
+            wait_ready(&device, &matches)?;
+            handle_every(device, &matches, submatches, &cli_features, &config)?;
         }
         Some(("manual", submatches)) => {
-            let device = device::Device::new(librazer::descriptor::Descriptor {
-                model_number_prefix: "Unknown",
-                name: "Unknown",
-                pid: *submatches.get_one::<u16>("pid").unwrap(),
-                features: feature::ALL_FEATURES,
-            })?;
-            handle(&device, submatches, &cli_features)?;
-        }
-        Some((cmd, _)) => unimplemented!("Subcommand not implemented: {}", cmd),
-        None => unreachable!(),
+            let pid = *submatches.get_one::<u16>("pid").unwrap();
+            ensure_pid_present(pid)?;
+            let mut descriptor = manual_descriptor(pid);
+            if let Some(requested) = submatches.get_many::<String>("features") {
+                descriptor.features = static_features(requested);
+            }
+            let mut device = device::Device::new(descriptor)?;
+            device.set_config(device_config);
+            wait_ready(&device, &matches)?;
+            handle_every(device, &matches, submatches, &cli_features, &config)?;
+        }
+        Some((cmd, _)) => anyhow::bail!("Subcommand not implemented: {}", cmd),
+        // subcommand_required normally rejects this before we get here
+        None => anyhow::bail!("No subcommand given, see --help"),
     };
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the feature commands as `auto` gets them, under a root with the global --query they
+    // refer to
+    fn feature_cmd() -> Command {
+        let root = Command::new("razer-cli").arg(arg!(--query).global(true));
+        update_cmd(root, &gen_cli_features(feature::ALL_FEATURES))
+    }
+
+    // `turbo`, `TURBO` and `Turbo` for a value named Turbo
+    fn spellings(value: &str) -> [String; 3] {
+        let mut capitalized: Vec<char> = value.to_lowercase().chars().collect();
+        capitalized[0] = capitalized[0].to_ascii_uppercase();
+        [
+            value.to_lowercase(),
+            value.to_uppercase(),
+            capitalized.into_iter().collect(),
+        ]
+    }
+
+    // (argument words, possible values) of every enum positional that is the only
+    // required argument of its command
+    fn enum_positionals(
+        cmd: &Command,
+        path: &[String],
+        found: &mut Vec<(Vec<String>, Vec<String>)>,
+    ) {
+        let mut positionals = cmd.get_positionals();
+        if let (Some(arg), None) = (positionals.next(), positionals.next()) {
+            let values: Vec<String> = arg
+                .get_possible_values()
+                .iter()
+                .map(|value| value.get_name().to_string())
+                .collect();
+            if !values.is_empty() {
+                found.push((path.to_vec(), values));
+            }
+        }
+        for sub in cmd.get_subcommands() {
+            let mut path = path.to_vec();
+            path.push(sub.get_name().to_string());
+            enum_positionals(sub, &path, found);
+        }
+    }
+
+    #[test]
+    fn enum_values_ignore_case() {
+        let mut found = Vec::new();
+        enum_positionals(&feature_cmd(), &[], &mut found);
+        assert!(found.iter().any(|(path, _)| path == &["perf", "mode"]));
+        for (path, values) in found {
+            for spelling in values.iter().flat_map(|value| spellings(value)) {
+                let args = ["razer-cli".to_string()]
+                    .into_iter()
+                    .chain(path.iter().cloned())
+                    .chain([spelling.clone()]);
+                if let Err(e) = feature_cmd().try_get_matches_from(args) {
+                    panic!("{} {}: {}", path.join(" "), spelling, e);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn perf_mode_in_any_case() {
+        for spelling in ["turbo", "TURBO", "Turbo", "tUrBo"] {
+            let matches = feature_cmd()
+                .try_get_matches_from(["razer-cli", "perf", "mode", spelling])
+                .unwrap();
+            let mode = matches
+                .subcommand_matches("perf")
+                .and_then(|perf| perf.subcommand_matches("mode"))
+                .and_then(|mode| mode.get_one::<PerfMode>("MODE"));
+            assert_eq!(mode, Some(&PerfMode::Turbo), "{}", spelling);
+        }
+    }
+
+    #[test]
+    fn verbosity_counts_only_verbose_flags() {
+        for (arg, verbosity) in [
+            ("-v", 1),
+            ("-vvv", 3),
+            ("--verbose", 1),
+            ("-", 0),
+            ("--", 0),
+            ("xv", 0),
+            ("v", 0),
+            ("-vx", 0),
+            ("é", 0),
+            ("-é", 0),
+            ("", 0),
+        ] {
+            assert_eq!(EarlyArgs::verbosity(arg), verbosity, "{:?}", arg);
+        }
+    }
+
+    #[test]
+    fn manual_features_keep_only_the_requested() {
+        let requested = ["perf".to_string(), "fan".to_string()];
+        let features = static_features(&requested);
+        assert_eq!(features.len(), 2);
+        assert!(features.iter().all(|f| requested.iter().any(|r| r == f)));
+        assert!(!features.contains(&"lid-logo"));
+    }
+
+    #[test]
+    fn fahrenheit_config_converts_curve_temperatures() {
+        let config: config::Config = toml::from_str("temperature_unit = \"fahrenheit\"").unwrap();
+        let unit = config.temperature_unit;
+        assert_eq!(unit, config::TemperatureUnit::Fahrenheit);
+        assert_eq!(unit.convert(100.0), 212.0);
+        assert_eq!(unit.symbol(), "°F");
+        let default: config::Config = toml::from_str("").unwrap();
+        assert_eq!(default.temperature_unit.convert(60.0), 60.0);
+    }
+
+    #[test]
+    fn nested_loops_share_the_ctrlc_handler() {
+        let mut outer_ticks = 0;
+        run_until_interrupted(std::time::Duration::from_secs(60), || {
+            outer_ticks += 1;
+            // like Ctrl-C during the inner loop
+            run_until_interrupted(std::time::Duration::from_secs(60), || {
+                interrupt();
+                Ok(())
+            })
+        })
+        .unwrap();
+        assert_eq!(outer_ticks, 1);
+    }
+}
@@ -0,0 +1,35 @@
+use log::{debug, info};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// set once from --notify before any feature is handled
+static DESKTOP: AtomicBool = AtomicBool::new(false);
+
+pub fn enable_desktop() {
+    DESKTOP.store(true, Ordering::Relaxed);
+}
+
+// Logs the message and, with --notify, also shows it as a desktop notification
+pub fn send(message: &str) {
+    info!("{}", message);
+    if DESKTOP.load(Ordering::Relaxed) {
+        if let Err(e) = desktop(message) {
+            debug!("Desktop notification unavailable: {}", e);
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn desktop(message: &str) -> anyhow::Result<()> {
+    use tauri_winrt_notification::Toast;
+    // razer-cli has no registered AppUserModelID, PowerShell's is always present
+    Toast::new(Toast::POWERSHELL_APP_ID)
+        .title(env!("CARGO_PKG_NAME"))
+        .text1(message)
+        .show()?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn desktop(_message: &str) -> anyhow::Result<()> {
+    anyhow::bail!("not implemented for this platform")
+}
@@ -0,0 +1,100 @@
+use crate::raw_value;
+use anyhow::Result;
+use librazer::command;
+use librazer::device::Device;
+use librazer::feature;
+use serde_json::{json, Value};
+
+// Values of every readable feature of the device, errors kept per feature
+fn read_features(device: &Device) -> Vec<(&'static str, Result<Value>)> {
+    device
+        .info
+        .features
+        .iter()
+        .filter_map(|&name| Some((name, feature::find(name).ok()?)))
+        .map(|(name, info)| (name, info.get(device)))
+        .collect()
+}
+
+// Power draw, clocks and throttling the OS reports, errors kept like those of the features
+fn telemetry(device: &Device) -> Value {
+    let value =
+        |value: Result<Value>| value.unwrap_or_else(|e| json!({ "error": format!("{:#}", e) }));
+    json!({
+        "cpu_power_w": value(command::get_cpu_power(device).map(|power| json!(power))),
+        "cpu_clock_mhz": value(command::get_cpu_clock(device).map(|clock| json!(clock))),
+        "gpu_power_w": value(command::get_gpu_power(device).map(|power| json!(power))),
+        "gpu_clock_mhz": value(command::get_gpu_clock(device).map(|clock| json!(clock))),
+        "throttle": value(command::get_throttle_state(device).map(|state| json!(state))),
+    })
+}
+
+pub fn json(device: &Device) -> Result<String> {
+    let features: serde_json::Map<_, _> = read_features(device)
+        .into_iter()
+        .map(|(name, value)| {
+            let value = value.unwrap_or_else(|e| json!({ "error": format!("{:#}", e) }));
+            (name.to_string(), value)
+        })
+        .collect();
+    let report = json!({
+        "device": {
+            "name": device.info.name,
+            "model_number_prefix": device.info.model_number_prefix,
+            "pid": device.info.pid,
+        },
+        "features": features,
+        "telemetry": telemetry(device),
+    });
+    Ok(serde_json::to_string_pretty(&report)?)
+}
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+// Cuts `text` to `width` characters, marking the cut
+fn truncate(text: &str, width: usize) -> String {
+    match text.chars().count() > width {
+        true => text
+            .chars()
+            .take(width.saturating_sub(1))
+            .chain(['…'])
+            .collect(),
+        false => text.to_string(),
+    }
+}
+
+// `width` is the terminal width to fit in, None when not printing to a terminal
+pub fn print_table(device: &Device, color: bool, width: Option<usize>) {
+    let mut rows = vec![
+        ("model".to_string(), Ok(device.info.name.to_string())),
+        ("pid".to_string(), Ok(format!("{:#06x}", device.info.pid))),
+    ];
+    rows.extend(read_features(device).into_iter().map(|(name, value)| {
+        (
+            name.to_string(),
+            value.map(|v| raw_value(&v)).map_err(|e| format!("{:#}", e)),
+        )
+    }));
+
+    let name_width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    let value_width = width.map_or(usize::MAX, |w| w.saturating_sub(name_width + 2).max(8));
+    let paint = |code: &str, text: String| match color {
+        true => format!("{}{}{}", code, text, RESET),
+        false => text,
+    };
+
+    println!(
+        "{}",
+        paint(BOLD, format!("{:<name_width$}  Value", "Feature"))
+    );
+    for (name, value) in rows {
+        let value = match value {
+            Ok(value) => paint(GREEN, truncate(&value, value_width)),
+            Err(e) => paint(RED, truncate(&format!("error: {}", e), value_width)),
+        };
+        println!("{:<name_width$}  {}", name, value);
+    }
+}
@@ -0,0 +1,275 @@
+// the protocol is platform independent, served on a Unix socket or a Windows named pipe
+#![cfg_attr(not(any(unix, windows)), allow(dead_code))]
+
+use crate::report;
+use anyhow::Result;
+use librazer::command;
+use librazer::device::Device;
+use librazer::feature;
+use librazer::types::{BatteryCare, FanMode, FanZone, PerfMode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// One request line, e.g. {"cmd": "set_fan_rpm", "args": {"rpm": 3200}}. `get` and `set`
+/// reach every feature through its generic accessors, the others are shortcuts for
+/// the commands front ends call most.
+#[derive(Deserialize)]
+#[serde(tag = "cmd", content = "args", rename_all = "snake_case")]
+enum Request {
+    Info,
+    Get { feature: String },
+    Set { feature: String, value: Value },
+    GetPerfState,
+    SetPerfMode { mode: PerfMode },
+    SetFanMode { mode: FanMode },
+    GetFanRpm,
+    SetFanRpm { rpm: u16 },
+    GetKeyboardBrightness,
+    SetKeyboardBrightness { brightness: u8 },
+    GetBatteryCare,
+    SetBatteryCare { mode: BatteryCare },
+}
+
+// Value for the "value" field of the response, null for setters
+fn dispatch(device: &Device, request: Request) -> Result<Value> {
+    Ok(match request {
+        Request::Info => serde_json::from_str(&report::json(device)?)?,
+        Request::Get { feature } => feature::get(device, &feature)?,
+        Request::Set { feature, value } => {
+            feature::set(device, &feature, value)?;
+            Value::Null
+        }
+        Request::GetPerfState => serde_json::to_value(command::get_perf_state(device)?)?,
+        Request::SetPerfMode { mode } => {
+            command::set_perf_mode(device, mode)?;
+            Value::Null
+        }
+        Request::SetFanMode { mode } => {
+            command::set_fan_mode(device, mode)?;
+            Value::Null
+        }
+        Request::GetFanRpm => json!(command::get_fan_rpm(device, FanZone::Zone1)?[0]),
+        Request::SetFanRpm { rpm } => {
+            command::set_fan_rpm(device, FanZone::All, rpm)?;
+            Value::Null
+        }
+        Request::GetKeyboardBrightness => json!(command::get_keyboard_brightness(device)?),
+        Request::SetKeyboardBrightness { brightness } => {
+            command::set_keyboard_brightness(device, brightness)?;
+            Value::Null
+        }
+        Request::GetBatteryCare => serde_json::to_value(command::get_battery_care(device)?)?,
+        Request::SetBatteryCare { mode } => {
+            command::set_battery_care(device, mode)?;
+            Value::Null
+        }
+    })
+}
+
+// {"ok": true} with the value of getters, or {"ok": false, "error": "..."}
+fn respond(device: &Device, line: &str) -> String {
+    let result = serde_json::from_str(line)
+        .map_err(anyhow::Error::from)
+        .and_then(|request| dispatch(device, request));
+    match result {
+        Ok(Value::Null) => json!({ "ok": true }),
+        Ok(value) => json!({ "ok": true, "value": value }),
+        Err(e) => json!({ "ok": false, "error": format!("{:#}", e) }),
+    }
+    .to_string()
+}
+
+/// Named pipe `serve` listens on by default on Windows
+#[cfg(windows)]
+pub const DEFAULT_PIPE: &str = r"\\.\pipe\razer-ctl";
+
+fn serve_client(
+    device: &Device,
+    reader: impl std::io::Read,
+    mut writer: impl std::io::Write,
+) -> Result<()> {
+    use std::io::{BufRead, BufReader};
+    for line in BufReader::new(reader).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        log::debug!("Request {}", line);
+        writeln!(writer, "{}", respond(device, &line))?;
+    }
+    Ok(())
+}
+
+/// Answers line-delimited JSON requests on a Unix socket at `path` until killed, one
+/// thread per client, all sharing the already open device. A socket file left behind
+/// by a previous run is replaced.
+#[cfg(unix)]
+pub fn run(device: &Device, path: &std::path::Path) -> Result<()> {
+    use anyhow::Context;
+    use std::os::unix::fs::FileTypeExt;
+    if std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+    let listener = std::os::unix::net::UnixListener::bind(path)
+        .with_context(|| format!("Failed to listen on {}", path.display()))?;
+    log::info!("Listening on {}", path.display());
+    std::thread::scope(|scope| {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    scope.spawn(move || {
+                        let served = stream
+                            .try_clone()
+                            .map_err(anyhow::Error::from)
+                            .and_then(|writer| serve_client(device, stream, writer));
+                        if let Err(e) = served {
+                            log::warn!("Client disconnected: {:#}", e);
+                        }
+                    });
+                }
+                Err(e) => log::warn!("Failed to accept a client: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Like the Unix `run`, on the named pipe `path`, e.g. [`DEFAULT_PIPE`]. Each client gets
+/// its own instance of the pipe. Remote clients are refused.
+#[cfg(windows)]
+pub fn run(device: &Device, path: &std::path::Path) -> Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    anyhow::ensure!(
+        path.to_str().is_some_and(|p| p.starts_with(r"\\.\pipe\")),
+        "{} is not a named pipe, e.g. {}",
+        path.display(),
+        DEFAULT_PIPE
+    );
+    let name: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+    log::info!("Listening on {}", path.display());
+    std::thread::scope(|scope| loop {
+        match pipe::accept(&name) {
+            Ok(pipe) => {
+                scope.spawn(move || {
+                    let served = pipe
+                        .try_clone()
+                        .map_err(anyhow::Error::from)
+                        .and_then(|writer| serve_client(device, pipe, writer));
+                    if let Err(e) = served {
+                        log::warn!("Client disconnected: {:#}", e);
+                    }
+                });
+            }
+            Err(e) => {
+                // e.g. the name is taken by another server, which would fail every time
+                anyhow::bail!("Failed to listen on {}: {}", path.display(), e)
+            }
+        }
+    })
+}
+
+#[cfg(windows)]
+mod pipe {
+    use std::fs::File;
+    use std::os::windows::io::FromRawHandle;
+    use windows_sys::Win32::Foundation::{
+        GetLastError, ERROR_PIPE_CONNECTED, INVALID_HANDLE_VALUE,
+    };
+    use windows_sys::Win32::Storage::FileSystem::PIPE_ACCESS_DUPLEX;
+    use windows_sys::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS,
+        PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+    };
+
+    const BUFFER_SIZE: u32 = 4096;
+
+    // Creates another instance of the pipe `name`, NUL terminated UTF-16, and waits for a
+    // client to connect to it
+    pub fn accept(name: &[u16]) -> std::io::Result<File> {
+        // SAFETY: name is NUL terminated, null security attributes take the default
+        let handle = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                PIPE_ACCESS_DUPLEX,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                BUFFER_SIZE,
+                BUFFER_SIZE,
+                0,
+                std::ptr::null(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(std::io::Error::last_os_error());
+        }
+        // SAFETY: the handle is valid and owned by nothing else, the file closes it
+        let pipe = unsafe { File::from_raw_handle(handle) };
+        // SAFETY: a blocking wait on the handle above, no overlapped IO
+        if unsafe { ConnectNamedPipe(handle, std::ptr::null_mut()) } == 0 {
+            // a client connecting before the wait started is connected already
+            // SAFETY: reads the calling thread's last error only
+            if unsafe { GetLastError() } != ERROR_PIPE_CONNECTED {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(pipe)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn run(_device: &Device, _path: &std::path::Path) -> Result<()> {
+    anyhow::bail!(librazer::Error::Unsupported(
+        "serve needs Unix sockets or Windows named pipes".into()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use librazer::mock::{MockTransport, Reply};
+
+    fn response(device: &Device, line: &str) -> Value {
+        serde_json::from_str(&respond(device, line)).unwrap()
+    }
+
+    #[test]
+    fn getters_answer_with_their_value() {
+        let transport = MockTransport::default();
+        transport.reply(Reply::Args(vec![1, 5, 128]));
+        let response = response(&transport.device(), r#"{"cmd": "get_keyboard_brightness"}"#);
+        assert_eq!(response, json!({ "ok": true, "value": 128 }));
+        assert_eq!(transport.commands(3), [(0x0383, vec![1, 5, 0])]);
+    }
+
+    #[test]
+    fn setters_answer_ok_without_a_value() {
+        let transport = MockTransport::default();
+        let line = r#"{"cmd": "set_keyboard_brightness", "args": {"brightness": 64}}"#;
+        assert_eq!(response(&transport.device(), line), json!({ "ok": true }));
+        assert_eq!(transport.commands(3), [(0x0303, vec![1, 5, 64])]);
+    }
+
+    #[test]
+    fn unknown_commands_are_refused_without_reaching_the_device() {
+        let transport = MockTransport::default();
+        let response = response(&transport.device(), r#"{"cmd": "reboot"}"#);
+        assert_eq!(response["ok"], false);
+        let error = response["error"].as_str().unwrap();
+        assert!(error.starts_with("unknown variant `reboot`"), "{}", error);
+        assert!(transport.sent().is_empty());
+    }
+
+    #[test]
+    fn malformed_json_is_an_error_response() {
+        let transport = MockTransport::default();
+        let response = response(&transport.device(), r#"{"cmd": "get_fan_rpm""#);
+        assert_eq!(response["ok"], false);
+        let error = response["error"].as_str().unwrap();
+        assert!(
+            error.starts_with("EOF while parsing an object"),
+            "{}",
+            error
+        );
+        assert!(transport.sent().is_empty());
+    }
+}
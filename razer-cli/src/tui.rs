@@ -0,0 +1,266 @@
+use anyhow::Result;
+use librazer::command;
+use librazer::device::Device;
+use librazer::types::{BatteryCare, CpuBoost, FanMode, FanZone, GpuBoost, PerfMode, PerfState};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Gauge, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+const RPM_STEP: u16 = 100;
+
+// Last read values, errors are kept as text so one failing getter doesn't hide the rest
+#[derive(Default)]
+struct State {
+    perf: Option<Result<PerfState, String>>,
+    rpm: Option<Result<u16, String>>,
+    boost: Option<Result<(CpuBoost, GpuBoost), String>>,
+    battery_care: Option<Result<BatteryCare, String>>,
+    kbd_backlight: Option<Result<u8, String>>,
+}
+
+struct App<'a> {
+    device: &'a Device,
+    state: State,
+    // RPM sent when switching to manual and moved by the slider
+    target_rpm: u16,
+    // what the firmware accepts, see command::get_fan_rpm_limits
+    rpm_range: RangeInclusive<u16>,
+    message: String,
+}
+
+fn text<T>(result: Result<T>) -> Result<T, String> {
+    result.map_err(|e| e.to_string())
+}
+
+fn show<T>(value: &Option<Result<T, String>>, format: impl Fn(&T) -> String) -> String {
+    match value {
+        Some(Ok(value)) => format(value),
+        Some(Err(e)) => format!("error: {}", e),
+        None => "n/a".to_string(),
+    }
+}
+
+impl<'a> App<'a> {
+    fn new(device: &'a Device) -> Result<Self> {
+        let (min, max) = command::get_fan_rpm_limits(device)?;
+        let mut app = Self {
+            device,
+            state: State::default(),
+            target_rpm: min,
+            rpm_range: min..=max,
+            message: String::new(),
+        };
+        app.refresh();
+        if let Some(Ok(rpm)) = app.state.rpm {
+            app.target_rpm = rpm.clamp(min, max);
+        }
+        Ok(app)
+    }
+
+    fn has(&self, feature: &str) -> bool {
+        self.device.info.features.contains(&feature)
+    }
+
+    fn refresh(&mut self) {
+        let device = self.device;
+        let has_perf = self.has("perf") || self.has("fan");
+        let perf = has_perf.then(|| text(command::get_perf_state(device)));
+        self.state = State {
+            rpm: match perf {
+                Some(Ok(PerfState {
+                    fan_mode: FanMode::Manual,
+                    ..
+                })) if self.has("fan") => Some(text(
+                    command::get_fan_rpm(device, FanZone::Zone1).map(|rpm| rpm[0]),
+                )),
+                _ => None,
+            },
+            boost: match perf {
+                Some(Ok(PerfState {
+                    perf_mode: PerfMode::Custom,
+                    ..
+                })) => Some(text((|| {
+                    Ok((
+                        command::get_cpu_boost(device)?,
+                        command::get_gpu_boost(device)?,
+                    ))
+                })())),
+                _ => None,
+            },
+            battery_care: self
+                .has("battery-care")
+                .then(|| text(command::get_battery_care(device))),
+            kbd_backlight: self
+                .has("kbd-backlight")
+                .then(|| text(command::get_keyboard_brightness(device))),
+            perf,
+        };
+    }
+
+    fn next_perf_mode(&self, current: PerfMode) -> PerfMode {
+        let modes = self.device.info.perf_modes;
+        let i = modes.iter().position(|&m| m == current).unwrap_or(0);
+        modes[(i + 1) % modes.len()]
+    }
+
+    // Returns false when the user asked to quit
+    fn on_key(&mut self, key: KeyCode) -> bool {
+        let device = self.device;
+        let perf = match self.state.perf {
+            Some(Ok(state)) => Some(state),
+            _ => None,
+        };
+        let result = match (key, perf) {
+            (KeyCode::Char('q') | KeyCode::Esc, _) => return false,
+            (KeyCode::Char('p'), Some(state)) if !device.info.perf_modes.is_empty() => {
+                let next = self.next_perf_mode(state.perf_mode);
+                command::set_perf_mode(device, next)
+                    .map(|_| format!("Performance mode set to {:?}", next))
+            }
+            (
+                KeyCode::Char('f'),
+                Some(PerfState {
+                    fan_mode: FanMode::Auto,
+                    ..
+                }),
+            ) => command::set_fan_mode(device, FanMode::Manual)
+                .and_then(|_| command::set_fan_rpm(device, FanZone::All, self.target_rpm))
+                .map(|_| format!("Fan set to {} RPM", self.target_rpm)),
+            (KeyCode::Char('f'), Some(_)) => {
+                command::set_fan_mode(device, FanMode::Auto).map(|_| "Fan set to Auto".to_string())
+            }
+            (KeyCode::Left | KeyCode::Right, perf) => {
+                self.target_rpm = match key {
+                    KeyCode::Left => self.target_rpm.saturating_sub(RPM_STEP),
+                    _ => self.target_rpm.saturating_add(RPM_STEP),
+                }
+                .clamp(*self.rpm_range.start(), *self.rpm_range.end());
+                match perf {
+                    Some(PerfState {
+                        fan_mode: FanMode::Manual,
+                        ..
+                    }) => command::set_fan_rpm(device, FanZone::All, self.target_rpm)
+                        .map(|_| format!("Fan set to {} RPM", self.target_rpm)),
+                    _ => Ok(format!("Fan RPM for manual mode: {}", self.target_rpm)),
+                }
+            }
+            (KeyCode::Char('b'), _) => match self.state.battery_care {
+                Some(Ok(care)) => {
+                    let toggled = match care {
+                        BatteryCare::Enable => BatteryCare::Disable,
+                        BatteryCare::Disable => BatteryCare::Enable,
+                    };
+                    command::set_battery_care(device, toggled)
+                        .map(|_| format!("Battery care set to {:?}", toggled))
+                }
+                _ => return true,
+            },
+            _ => return true,
+        };
+        self.message = result.unwrap_or_else(|e| format!("Error: {}", e));
+        self.refresh();
+        true
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let state = &self.state;
+        let lines = vec![
+            Line::from(format!(
+                "Performance:    {}",
+                show(&state.perf, |perf| format!("{:?}", perf.perf_mode))
+            )),
+            Line::from(format!(
+                "Fan:            {}",
+                show(&state.perf, |perf| format!("{:?}", perf.fan_mode))
+            )),
+            Line::from(format!(
+                "CPU/GPU boost:  {}",
+                show(&state.boost, |(cpu, gpu)| format!("{:?} / {:?}", cpu, gpu))
+            )),
+            Line::from(format!(
+                "Battery care:   {}",
+                show(&state.battery_care, |care| format!("{:?}", care))
+            )),
+            Line::from(format!(
+                "Kbd backlight:  {}",
+                show(&state.kbd_backlight, u8::to_string)
+            )),
+        ];
+
+        let [status_area, gauge_area, help_area] = Layout::vertical([
+            Constraint::Length(lines.len() as u16 + 2),
+            Constraint::Length(3),
+            Constraint::Min(3),
+        ])
+        .areas(frame.area());
+
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(self.device.info.name)),
+            status_area,
+        );
+
+        let rpm = match state.rpm {
+            Some(Ok(rpm)) => rpm,
+            _ => self.target_rpm,
+        };
+        let span = (self.rpm_range.end() - self.rpm_range.start()) as f64;
+        frame.render_widget(
+            Gauge::default()
+                .block(Block::bordered().title("Fan RPM"))
+                .gauge_style(Style::default().fg(match state.rpm {
+                    Some(Ok(_)) => Color::Green,
+                    _ => Color::DarkGray,
+                }))
+                .ratio((rpm.saturating_sub(*self.rpm_range.start()) as f64 / span).clamp(0.0, 1.0))
+                .label(match state.rpm {
+                    Some(Ok(rpm)) => format!("{} RPM", rpm),
+                    _ => format!("Auto ({} RPM when manual)", self.target_rpm),
+                }),
+            gauge_area,
+        );
+
+        frame.render_widget(
+            Paragraph::new(vec![
+                Line::from("p: next performance mode  f: fan auto/manual  ←/→: fan RPM  b: battery care  q: quit"),
+                Line::from(self.message.as_str()),
+            ])
+            .block(Block::bordered()),
+            help_area,
+        );
+    }
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, app: &mut App) -> Result<()> {
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        if !event::poll(REFRESH_INTERVAL)? {
+            app.refresh();
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press && !app.on_key(key.code) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+pub fn run(device: &Device) -> Result<()> {
+    // log lines would be drawn over the dashboard
+    let log_level = log::max_level();
+    log::set_max_level(log::LevelFilter::Off);
+
+    let mut app = App::new(device)?;
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut app);
+    ratatui::restore();
+
+    log::set_max_level(log_level);
+    result
+}
@@ -34,6 +34,7 @@ enum PerfMode {
     Silent,
     Balanced(FanSpeed),
     Turbo(FanSpeed),
+    Creator,
     Custom(CpuBoost, GpuBoost, MaxFanSpeedMode),
 }
 
@@ -53,22 +54,24 @@ struct DeviceState {
 
 impl DeviceState {
     fn read(device: &device::Device) -> Result<Self> {
-        let perf_mode = match command::get_perf_mode(device)? {
+        let state = command::get_perf_state(device)?;
+        let perf_mode = match (state.perf_mode, state.fan_mode) {
             (librazer::types::PerfMode::Silent, _) => PerfMode::Silent,
             (librazer::types::PerfMode::Balanced, librazer::types::FanMode::Auto) => {
                 PerfMode::Balanced(FanSpeed::Auto)
             }
             (librazer::types::PerfMode::Balanced, librazer::types::FanMode::Manual) => {
-                let fan_speed = command::get_fan_rpm(device, librazer::types::FanZone::Zone1)?;
+                let fan_speed = command::get_fan_rpm(device, librazer::types::FanZone::Zone1)?[0];
                 PerfMode::Balanced(FanSpeed::Manual(fan_speed))
             }
             (librazer::types::PerfMode::Turbo, librazer::types::FanMode::Auto) => {
                 PerfMode::Turbo(FanSpeed::Auto)
             }
             (librazer::types::PerfMode::Turbo, librazer::types::FanMode::Manual) => {
-                let fan_speed = command::get_fan_rpm(device, librazer::types::FanZone::Zone1)?;
+                let fan_speed = command::get_fan_rpm(device, librazer::types::FanZone::Zone1)?[0];
                 PerfMode::Turbo(FanSpeed::Manual(fan_speed))
             }
+            (librazer::types::PerfMode::Creator, _) => PerfMode::Creator,
             (librazer::types::PerfMode::Custom, _) => {
                 let cpu_boost = command::get_cpu_boost(device)?;
                 let gpu_boost = command::get_gpu_boost(device)?;
@@ -101,7 +104,7 @@ impl DeviceState {
             PerfMode::Balanced(FanSpeed::Manual(rpm)) => {
                 command::set_perf_mode(device, librazer::types::PerfMode::Balanced)?;
                 command::set_fan_mode(device, librazer::types::FanMode::Manual)?;
-                command::set_fan_rpm(device, rpm)
+                command::set_fan_rpm(device, librazer::types::FanZone::All, rpm)
             }
             PerfMode::Turbo(FanSpeed::Auto) => {
                 command::set_perf_mode(device, librazer::types::PerfMode::Turbo)
@@ -109,8 +112,9 @@ impl DeviceState {
             PerfMode::Turbo(FanSpeed::Manual(rpm)) => {
                 command::set_perf_mode(device, librazer::types::PerfMode::Turbo)?;
                 command::set_fan_mode(device, librazer::types::FanMode::Manual)?;
-                command::set_fan_rpm(device, rpm)
+                command::set_fan_rpm(device, librazer::types::FanZone::All, rpm)
             }
+            PerfMode::Creator => command::set_perf_mode(device, librazer::types::PerfMode::Creator),
             PerfMode::Custom(cpu_boost, gpu_boost, max_fan_speed) => {
                 command::set_perf_mode(device, librazer::types::PerfMode::Custom)?;
                 command::set_cpu_boost(device, cpu_boost)?;
@@ -530,7 +534,7 @@ impl ProgramState {
                 PerfMode::Balanced(..) => {
                     PerfMode::Turbo(FanSpeed::Auto)
                 }
-                PerfMode::Turbo(..) => {
+                PerfMode::Turbo(..) | PerfMode::Creator => {
                     PerfMode::Custom(CpuBoost::Boost, GpuBoost::High, MaxFanSpeedMode::Disable)
                 }
                 PerfMode::Custom(..) => PerfMode::Silent,
@@ -558,6 +562,7 @@ impl ProgramState {
             PerfMode::Turbo(FanSpeed::Manual(rpm)) => {
                 writeln!(&mut info, "Turbo {}", rpm)?;
             }
+            PerfMode::Creator => writeln!(&mut info, "Creator")?,
             PerfMode::Custom(cpu_boost, gpu_boost, max_fan_speed) => {
                 writeln!(&mut info, "Custom",)?;
                 if max_fan_speed == MaxFanSpeedMode::Enable {
@@ -603,6 +608,7 @@ impl ProgramState {
             PerfMode::Balanced(_) => image::load_from_memory(razer_green),
             // TODO: Add new Turbo icon
             PerfMode::Turbo(_) => image::load_from_memory(razer_red),
+            PerfMode::Creator => image::load_from_memory(razer_green),
             PerfMode::Custom(_, _, _) => image::load_from_memory(razer_red),
         };
 